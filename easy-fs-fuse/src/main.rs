@@ -1,9 +1,10 @@
 use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem, set_console_putchar};
+use easy_fs::{BlockDevice, EasyFileSystem, set_clock, set_console_putchar};
 use std::fs::{read_dir, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const BLOCK_SZ: usize = 512;
 
@@ -27,8 +28,13 @@ impl BlockDevice for BlockFile {
 fn putchar(c:usize){
     println!("{}",c as u8 as char);
 }
+/// Seconds since the Unix epoch, for the timestamps of the packed files
+fn current_time() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32
+}
 fn main() {
     set_console_putchar(putchar as *const());
+    set_clock(current_time as *const ());
     easy_fs_pack().expect("Error when packing easy-fs!");
 }
 
@@ -58,7 +64,8 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(output_path)?;
-        f.set_len(16384 * 512).unwrap();
+        // 16384 blocks of filesystem followed by 8192 blocks of swap region
+        f.set_len((16384 + 8192) * 512).unwrap();
         f
     })));
     // 4MiB, at most 4095 files
@@ -75,7 +82,9 @@ fn easy_fs_pack() -> std::io::Result<()> {
         let name = path.file_stem().unwrap().to_str().unwrap();
         let inode = root_inode.create(name).unwrap();
         // write data to easy-fs
-        inode.write_at(0, all_data.as_slice());
+        inode
+            .write_at(0, all_data.as_slice())
+            .expect("no space left in the image");
     }
     // list apps
     for app in root_inode.ls() {
@@ -8,7 +8,6 @@ use super::{
     DiskInodeType,
     Inode,
     get_block_cache,
-    block_cache_sync_all,
 };
 use crate::{BLOCK_SZ, hello_world_in_easy_fs, println};
 #[must_use]
@@ -78,7 +77,7 @@ impl EasyFileSystem {
         });
         // write back immediately
         // create a inode for root node "/"
-        assert_eq!(efs.alloc_inode(), 0);
+        assert_eq!(efs.alloc_inode(), Some(0));
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
         get_block_cache(
             root_inode_block_id as usize,
@@ -88,8 +87,10 @@ impl EasyFileSystem {
         .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
             disk_inode.initialize(DiskInodeType::Directory);
         });
-        block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        let efs = Arc::new(Mutex::new(efs));
+        // "." and ".." of the root both point to itself
+        Self::root_inode(&efs).init_root();
+        efs
     }
     /// Open a block device as a filesystem
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
@@ -142,13 +143,19 @@ impl EasyFileSystem {
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
     }
-    /// Allocate a new inode
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    /// Allocate a new inode, None if all inodes are in use
+    pub fn alloc_inode(&mut self) -> Option<u32> {
+        self.inode_bitmap.alloc(&self.block_device).map(|id| id as u32)
     }
-    /// Allocate a data block
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// Deallocate an inode
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap.dealloc(&self.block_device, inode_id as usize)
+    }
+    /// Allocate a data block, None if the device is full
+    pub fn alloc_data(&mut self) -> Option<u32> {
+        self.data_bitmap
+            .alloc(&self.block_device)
+            .map(|id| id as u32 + self.data_area_start_block)
     }
     /// Deallocate a data block
     pub fn dealloc_data(&mut self, block_id: u32) {
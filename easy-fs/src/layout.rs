@@ -2,6 +2,7 @@ use core::fmt::{Debug, Formatter, Result};
 use super::{
     BLOCK_SZ,
     BlockDevice,
+    current_time,
     get_block_cache,
 };
 use alloc::sync::Arc;
@@ -10,10 +11,12 @@ use crate::println;
 
 /// Magic number for sanity check
 const EFS_MAGIC: u32 = 0x3b800001;
+/// Version of the on-disk format, 2 added the timestamps, mode and owner of inodes
+const EFS_VERSION: u32 = 2;
 /// The max number of direct inodes
-const INODE_DIRECT_COUNT: usize = 27;
+const INODE_DIRECT_COUNT: usize = 22;
 /// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 27;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// The max number of indirect2 inodes
@@ -23,13 +26,15 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode index
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+/// The max size of a file in bytes
+pub const MAX_FILE_SIZE: usize = INDIRECT2_BOUND * BLOCK_SZ;
 
 /// Super block of a filesystem
 #[repr(C)]
 pub struct SuperBlock {
     magic: u32,
+    version: u32,
     pub total_blocks: u32,
     pub inode_bitmap_blocks: u32,
     pub inode_area_blocks: u32,
@@ -40,6 +45,7 @@ pub struct SuperBlock {
 impl Debug for SuperBlock {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         f.debug_struct("SuperBlock")
+            .field("version", &self.version)
             .field("total_blocks", &self.total_blocks)
             .field("inode_bitmap_blocks", &self.inode_bitmap_blocks)
             .field("inode_area_blocks", &self.inode_area_blocks)
@@ -61,6 +67,7 @@ impl SuperBlock {
     ) {
         *self = Self {
             magic: EFS_MAGIC,
+            version: EFS_VERSION,
             total_blocks,
             inode_bitmap_blocks,
             inode_area_blocks,
@@ -68,9 +75,9 @@ impl SuperBlock {
             data_area_blocks,
         }
     }
-    /// Check if a super block is valid using efs magic
+    /// Check if a super block is valid using efs magic and the format version
     pub fn is_valid(&self) -> bool {
-        self.magic == EFS_MAGIC
+        self.magic == EFS_MAGIC && self.version == EFS_VERSION
     }
 }
 
@@ -79,6 +86,8 @@ impl SuperBlock {
 pub enum DiskInodeType {
     File,
     Directory,
+    /// A symbolic link, its data is the target path
+    Symlink,
 }
 
 /// A indirect block
@@ -95,6 +104,16 @@ pub struct DiskInode {
     //保证还是128字节
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Time of last access in seconds
+    pub atime: u32,
+    /// Time of last modification of the data
+    pub mtime: u32,
+    /// Time of last change of the data or the metadata
+    pub ctime: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Permission bits
+    pub mode: u16,
     type_: DiskInodeType,
 }
 
@@ -107,6 +126,18 @@ impl DiskInode {
         self.indirect1 = 0;
         self.indirect2 = 0;
         self.hard_link = 1;
+        let now = current_time();
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+        // there are no users yet, everything belongs to root
+        self.uid = 0;
+        self.gid = 0;
+        self.mode = match type_ {
+            DiskInodeType::File => 0o644,
+            DiskInodeType::Directory => 0o755,
+            DiskInodeType::Symlink => 0o777,
+        };
         self.type_ = type_;
     }
     /// Whether this inode is a directory
@@ -118,6 +149,10 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether this inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::Symlink
+    }
     /// Get the number of data blocks corresponding to size
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
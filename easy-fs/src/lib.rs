@@ -9,6 +9,7 @@ mod efs;
 mod bitmap;
 mod vfs;
 mod block_cache;
+mod clock;
 
 #[macro_use]
 mod console;
@@ -17,12 +18,14 @@ mod console;
 pub const BLOCK_SZ: usize = 512;
 pub use block_dev::BlockDevice;
 pub use efs::EasyFileSystem;
-pub use vfs::Inode;
-pub use layout::DiskInodeType;
+pub use vfs::{Inode, InodeStat};
+pub use layout::{DiskInodeType, DIRENT_SZ, MAX_FILE_SIZE, NAME_LENGTH_LIMIT};
 use layout::*;
 use bitmap::Bitmap;
 use block_cache::{get_block_cache, block_cache_sync_all};
 pub use console::set_console_putchar;
+pub use clock::set_clock;
+use clock::current_time;
 
 pub fn hello_world_in_easy_fs() {
     println!("Hello, world in easy fs!");
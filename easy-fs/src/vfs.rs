@@ -5,15 +5,38 @@ use super::{
     DirEntry,
     EasyFileSystem,
     DIRENT_SZ,
+    MAX_FILE_SIZE,
+    NAME_LENGTH_LIMIT,
+    current_time,
     get_block_cache,
     block_cache_sync_all,
 };
 use alloc::sync::Arc;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
 use crate::{BLOCK_SZ, println};
 
+/// Like relatime, the access time is only refreshed once a day unless the file changed since
+const ATIME_INTERVAL: u32 = 24 * 60 * 60;
+
+
+/// Metadata of an inode returned by [`Inode::stat`], times are in seconds
+pub struct InodeStat {
+    pub inode_id: u32,
+    pub nlink: u32,
+    pub type_: DiskInodeType,
+    /// Size in bytes
+    pub size: u32,
+    /// Permission bits
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+}
 
 /// Virtual filesystem layer over easy-fs
 pub struct Inode {
@@ -80,6 +103,34 @@ impl Inode {
         }
         None
     }
+    /// Rewrite the dirent `name` of a directory disk inode to `new_name` pointing to `inode_id`
+    fn rewrite_dir_entry(
+        &self,
+        name: &str,
+        new_name: &str,
+        inode_id: u32,
+        disk_inode: &mut DiskInode,
+    ) {
+        assert!(disk_inode.is_dir());
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        for i in 0..file_count {
+            assert_eq!(
+                disk_inode.read_at(
+                    DIRENT_SZ * i,
+                    dirent.as_bytes_mut(),
+                    &self.block_device,
+                ),
+                DIRENT_SZ,
+            );
+            if dirent.name() == name {
+                let dirent = DirEntry::new(new_name, inode_id);
+                disk_inode.write_at(DIRENT_SZ * i, dirent.as_bytes(), &self.block_device);
+                return;
+            }
+        }
+        panic!("no such file");
+    }
     //采用交换策略,保证目录下面至少有一个文件,否则panic
     fn remove_dir_entry(&self, name: &str, disk_inode: &mut DiskInode) {
         assert!(disk_inode.is_dir());
@@ -120,6 +171,17 @@ impl Inode {
         self.decrease_size(disk_inode.size-DIRENT_SZ as u32 , disk_inode);
     }
 
+    /// Get the vfs inode of `inode_id` while holding the fs lock
+    fn get_inode(&self, inode_id: u32, fs: &MutexGuard<EasyFileSystem>) -> Inode {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+            inode_id,
+        )
+    }
     /// Find inode under current inode by name
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
@@ -137,22 +199,122 @@ impl Inode {
                 })
         })
     }
-    /// Increase the size of a disk inode
+    /// Find inode by path starting from current inode,
+    /// a path beginning with `/` starts from the root instead
+    pub fn find_path(self: &Arc<Self>, path: &str) -> Option<Arc<Inode>> {
+        let mut inode = if path.starts_with('/') {
+            Arc::new(EasyFileSystem::root_inode(&self.fs))
+        } else {
+            Arc::clone(self)
+        };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if !inode.is_dir() {
+                return None;
+            }
+            inode = inode.find(name)?;
+        }
+        Some(inode)
+    }
+    /// Find the name of the entry pointing to `inode_id` under current inode,
+    /// skipping `.` and `..`
+    pub fn find_name(&self, inode_id: u32) -> Option<String> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            assert!(disk_inode.is_dir());
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            let mut dirent = DirEntry::empty();
+            for i in 0..file_count {
+                assert_eq!(
+                    disk_inode.read_at(
+                        DIRENT_SZ * i,
+                        dirent.as_bytes_mut(),
+                        &self.block_device,
+                    ),
+                    DIRENT_SZ,
+                );
+                let name = dirent.name();
+                if dirent.inode_number() == inode_id && name != "." && name != ".." {
+                    return Some(String::from(name));
+                }
+            }
+            None
+        })
+    }
+    /// Get the inode number of current inode
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+    /// Whether current inode is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+    /// Whether current inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+    /// Append a dirent pointing to `inode_id` to a directory disk inode,
+    /// None if the device is full
+    fn append_dir_entry(
+        &self,
+        name: &str,
+        inode_id: u32,
+        disk_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Option<()> {
+        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+        let new_size = (file_count + 1) * DIRENT_SZ;
+        self.increase_size(new_size as u32, disk_inode, fs)?;
+        let dirent = DirEntry::new(name, inode_id);
+        disk_inode.write_at(
+            file_count * DIRENT_SZ,
+            dirent.as_bytes(),
+            &self.block_device,
+        );
+        Some(())
+    }
+    /// Fill an empty directory with its `.` and `..` entries, None if the device is full
+    fn init_dir_entries(&self, parent_inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) -> Option<()> {
+        self.modify_disk_inode(|disk_inode| {
+            // both entries share the first data block
+            self.append_dir_entry(".", self.inode_id, disk_inode, fs)?;
+            self.append_dir_entry("..", parent_inode_id, disk_inode, fs)
+        })
+    }
+    /// Initialize the root directory, whose parent is itself
+    pub(crate) fn init_root(&self) {
+        let mut fs = self.fs.lock();
+        self.init_dir_entries(self.inode_id, &mut fs)
+            .expect("no space for the root directory");
+        block_cache_sync_all();
+    }
+    /// Increase the size of a disk inode, None if the blocks cannot be allocated,
+    /// then the disk inode is not changed
     fn increase_size(
         &self,
         new_size: u32,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> Option<()> {
         if new_size < disk_inode.size {
-            return;
+            return Some(());
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..blocks_needed {
-            v.push(fs.alloc_data());
+            match fs.alloc_data() {
+                Some(block_id) => v.push(block_id),
+                None => {
+                    for block_id in v {
+                        fs.dealloc_data(block_id);
+                    }
+                    return None;
+                }
+            }
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        Some(())
     }
     fn decrease_size(&self, new_size: u32, disk_inode: &mut DiskInode) {
         if new_size > disk_inode.size {
@@ -166,6 +328,40 @@ impl Inode {
     }
     /// Create inode under current inode by name
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+    /// Create a directory under current inode by name
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+    /// Create a symbolic link to `target` under current inode by name
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        let inode = self.create_inode(name, DiskInodeType::Symlink)?;
+        if inode.write_at(0, target.as_bytes()).is_none() {
+            // no space for the target, do not leave an empty link behind
+            self.unlink(name);
+            return None;
+        }
+        Some(inode)
+    }
+    /// The target path of current inode, None if it is not a symbolic link
+    pub fn read_link(&self) -> Option<String> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut target = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut target, &self.block_device);
+            String::from_utf8(target).ok()
+        })
+    }
+    /// Create an inode of the given type under current inode,
+    /// None if `name` exists or the device is full
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
+        if name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
         let mut fs = self.fs.lock();
         if self.modify_disk_inode(|root_inode| {
             // assert it is a directory
@@ -177,7 +373,7 @@ impl Inode {
         }
         // create a new file
         // alloc a inode with an indirect block
-        let new_inode_id = fs.alloc_inode();
+        let new_inode_id = fs.alloc_inode()?;
         // initialize inode
         let (new_inode_block_id, new_inode_block_offset)
             = fs.get_disk_inode_pos(new_inode_id);
@@ -185,33 +381,36 @@ impl Inode {
             new_inode_block_id as usize,
             Arc::clone(&self.block_device),
         ).lock().modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-            new_inode.initialize(DiskInodeType::File);
-        });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+            new_inode.initialize(type_);
         });
-
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
-        // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
+        let new_inode = Self::new(
+            new_inode_block_id,
+            new_inode_block_offset,
             self.fs.clone(),
             self.block_device.clone(),
             new_inode_id,
-        )))
+        );
+        let created = if type_ == DiskInodeType::Directory {
+            new_inode.init_dir_entries(self.inode_id, &mut fs)
+        } else {
+            Some(())
+        }
+        .and_then(|_| {
+            self.modify_disk_inode(|root_inode| {
+                // append file in the dirent
+                self.append_dir_entry(name, new_inode_id, root_inode, &mut fs)
+            })
+        });
+        if created.is_none() {
+            // the device is full, release what the new inode holds
+            new_inode.clearInternal(&mut fs);
+            fs.dealloc_inode(new_inode_id);
+            block_cache_sync_all();
+            return None;
+        }
+        block_cache_sync_all();
+        // return inode
+        Some(Arc::new(new_inode))
         // release efs lock automatically by compiler
     }
     /// List inodes under current inode
@@ -235,41 +434,133 @@ impl Inode {
             v
         })
     }
-    /// 给出这个inode对应的inode以及hardlink,.0 是inode,.1是nlink
-    pub fn stat(&self) -> (u32, u32, DiskInodeType) {
+    /// The entries of current directory from the `start`-th one,
+    /// as (name, inode number, type of the inode)
+    pub fn read_dir(&self, start: usize) -> Vec<(String, u32, DiskInodeType)> {
+        let fs = self.fs.lock();
+        let entries: Vec<(String, u32)> = self.read_disk_inode(|disk_inode| {
+            assert!(disk_inode.is_dir());
+            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
+            let mut v = Vec::new();
+            for i in start..file_count {
+                let mut dirent = DirEntry::empty();
+                assert_eq!(
+                    disk_inode.read_at(
+                        i * DIRENT_SZ,
+                        dirent.as_bytes_mut(),
+                        &self.block_device,
+                    ),
+                    DIRENT_SZ,
+                );
+                v.push((String::from(dirent.name()), dirent.inode_number()));
+            }
+            v
+        });
+        entries
+            .into_iter()
+            .map(|(name, inode_id)| {
+                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+                let type_ = get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                    .lock()
+                    .read(block_offset, |disk_inode: &DiskInode| disk_inode.INodeType());
+                (name, inode_id, type_)
+            })
+            .collect()
+    }
+    /// 给出这个inode的元数据: inode号, hardlink数, 类型, 时间戳, 权限和所有者
+    pub fn stat(&self) -> InodeStat {
         let _fs = self.fs.lock();
-        let mut nlink = 0;
-        let mut typ = DiskInodeType::File;
         self.read_disk_inode(|disk_inode| {
             if disk_inode.hard_link==0 {
                 println!("stat  hard_link is 0");
             }
-            nlink = disk_inode.hard_link;
-            typ = disk_inode.INodeType();
+            InodeStat {
+                inode_id: self.inode_id,
+                nlink: disk_inode.hard_link,
+                type_: disk_inode.INodeType(),
+                size: disk_inode.size,
+                mode: disk_inode.mode,
+                uid: disk_inode.uid,
+                gid: disk_inode.gid,
+                atime: disk_inode.atime,
+                mtime: disk_inode.mtime,
+                ctime: disk_inode.ctime,
+            }
+        })
+    }
+    /// Change the permission bits of current inode
+    pub fn set_mode(&self, mode: u16) {
+        let _fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = mode;
+            disk_inode.ctime = current_time();
         });
-
-        return (self.inode_id, nlink, typ);
+        block_cache_sync_all();
     }
-    /// Read data from current inode
+    /// Set the access and modification times of current inode, `None` keeps the old one
+    pub fn set_times(&self, atime: Option<u32>, mtime: Option<u32>) {
+        let _fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            if let Some(atime) = atime {
+                disk_inode.atime = atime;
+            }
+            if let Some(mtime) = mtime {
+                disk_inode.mtime = mtime;
+            }
+            disk_inode.ctime = current_time();
+        });
+        block_cache_sync_all();
+    }
+    /// Size of the file in bytes
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+    /// Read data from current inode, the access time is updated lazily
+    /// (see [`ATIME_INTERVAL`]) to avoid writing the inode on every read
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            disk_inode.read_at(offset, buf, &self.block_device)
-        })
+        let now = current_time();
+        let (size, stale) = self.read_disk_inode(|disk_inode| {
+            let stale = disk_inode.atime != now
+                && (disk_inode.atime <= disk_inode.mtime
+                    || disk_inode.atime <= disk_inode.ctime
+                    || now.wrapping_sub(disk_inode.atime) >= ATIME_INTERVAL);
+            (disk_inode.read_at(offset, buf, &self.block_device), stale)
+        });
+        if stale {
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
+        size
     }
-    /// Write data to current inode
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+    /// Write data to current inode, the modification and change times are updated.
+    ///
+    /// Returns None and writes nothing if the file would grow beyond
+    /// [`MAX_FILE_SIZE`] or the device is full
+    pub fn write_at(&self, offset: usize, buf: &[u8]) -> Option<usize> {
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| end <= MAX_FILE_SIZE)?;
          let mut fs = self.fs.lock();
         let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+            self.increase_size(end as u32, disk_inode, &mut fs)?;
+            let now = current_time();
+            disk_inode.mtime = now;
+            disk_inode.ctime = now;
+            Some(disk_inode.write_at(offset, buf, &self.block_device))
         });
         block_cache_sync_all();
         size
     }
+    /// Truncate current inode to size 0, the modification and change times are updated
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
          self.clearInternal(&mut fs);
+        self.modify_disk_inode(|disk_inode| {
+            let now = current_time();
+            disk_inode.mtime = now;
+            disk_inode.ctime = now;
+        });
         block_cache_sync_all();
     }
     /// Clear the data in current inode
@@ -287,26 +578,23 @@ impl Inode {
             }
         });
     }
-    pub fn link(&self, old_name: &str, new_name: &str) -> Option<()> {
-        // println!("link {} {}",old_name,new_name);
-        let old_inode = self.find(old_name)?;
+    /// Create a hard link named `name` under current inode to `inode`
+    pub fn link(&self, name: &str, inode: &Inode) -> Option<()> {
+        if name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
         let mut fs = self.fs.lock();
+        if self.read_disk_inode(|root_inode| {
+            self.find_inode_id(name, root_inode)
+        }).is_some() {
+            return None;
+        }
+        // both disk inodes may live in the same block, so never modify them nested
         self.modify_disk_inode(|root_inode| {
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            let dirent = DirEntry::new(new_name, old_inode.inode_id);
-            let mut hard_link=0;
-            old_inode.modify_disk_inode(|disk_inode| {
-                disk_inode.hard_link += 1;
-                hard_link = disk_inode.hard_link;
-            });
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-            // println!("{} link={}",old_name,hard_link);
+            self.append_dir_entry(name, inode.inode_id, root_inode, &mut fs)
+        })?;
+        inode.modify_disk_inode(|disk_inode| {
+            disk_inode.hard_link += 1;
         });
         block_cache_sync_all();
         Some(())
@@ -326,19 +614,70 @@ impl Inode {
         self.modify_disk_inode(|root_inode| {
             self.remove_dir_entry(name,root_inode);
         });
-        let mut need_clear=false ;
-        let mut hard_link=0;
-        old_inode.modify_disk_inode(|disk_inode| {
+        //2. 然后删除文件的数据块
+        old_inode.drop_link(&mut fs);
+        block_cache_sync_all();
+        Some(())
+    }
+    /// Drop a hard link to current inode, the data is released with the last link
+    fn drop_link(&self, fs: &mut MutexGuard<EasyFileSystem>) {
+        let mut need_clear = false;
+        self.modify_disk_inode(|disk_inode| {
             disk_inode.hard_link -= 1;
-            if disk_inode.hard_link<=0 {
-                need_clear=true;
-            }
-            hard_link = disk_inode.hard_link;
+            need_clear = disk_inode.hard_link == 0;
         });
-        // println!("{} unlink={}",name,hard_link);
-        //2. 然后删除文件的数据块
-        if need_clear{
-            old_inode.clearInternal(&mut fs );
+        if need_clear {
+            self.clearInternal(fs);
+        }
+    }
+    /// Move the entry `old` of current directory to `new_name` under `new_parent`.
+    ///
+    /// An existing `new_name` is replaced by rewriting its dirent in place, so the
+    /// name always points to one of the two inodes. The caller checks whether the
+    /// target may be replaced
+    pub fn rename(&self, old: &str, new_parent: &Inode, new_name: &str) -> Option<()> {
+        if new_name.len() > NAME_LENGTH_LIMIT {
+            return None;
+        }
+        let mut fs = self.fs.lock();
+        let inode_id = self.read_disk_inode(|disk_inode| {
+            self.find_inode_id(old, disk_inode)
+        })?;
+        let target_id = new_parent.read_disk_inode(|disk_inode| {
+            new_parent.find_inode_id(new_name, disk_inode)
+        });
+        // both names are links to the same inode
+        if target_id == Some(inode_id) {
+            return Some(());
+        }
+        let same_dir = self.inode_id == new_parent.inode_id;
+        if same_dir && target_id.is_none() {
+            self.modify_disk_inode(|dir_inode| {
+                self.rewrite_dir_entry(old, new_name, inode_id, dir_inode);
+            });
+        } else {
+            // the directories may live in the same block, so never modify them nested
+            new_parent.modify_disk_inode(|dir_inode| {
+                if target_id.is_some() {
+                    new_parent.rewrite_dir_entry(new_name, new_name, inode_id, dir_inode);
+                    Some(())
+                } else {
+                    new_parent.append_dir_entry(new_name, inode_id, dir_inode, &mut fs)
+                }
+            })?;
+            self.modify_disk_inode(|dir_inode| {
+                self.remove_dir_entry(old, dir_inode);
+            });
+        }
+        // a moved directory has a new parent
+        let inode = self.get_inode(inode_id, &fs);
+        if !same_dir && inode.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            inode.modify_disk_inode(|dir_inode| {
+                inode.rewrite_dir_entry("..", "..", new_parent.inode_id, dir_inode);
+            });
+        }
+        if let Some(target_id) = target_id {
+            self.get_inode(target_id, &fs).drop_link(&mut fs);
         }
         block_cache_sync_all();
         Some(())
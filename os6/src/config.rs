@@ -9,8 +9,18 @@ pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
+/// Trap context of the main thread, the one of thread `tid` is `tid` pages below it
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// Top of the user stack of the main thread, the stack of thread `tid` is
+/// `tid` stacks and guard pages below it
+pub const USER_STACK_TOP: usize = (1 << 38) - PAGE_SIZE;
 pub const CLOCK_FREQ: usize = 12500000;
+/// the swap region lives on the block device right after the filesystem
+pub const SWAP_START_BLOCK: usize = 16384;
+pub const SWAP_PAGES: usize = 1024;
+/// goldfish RTC of the qemu virt board, nanoseconds since the Unix epoch
+pub const RTC_BASE: usize = 0x101000;
 pub const MMIO: &[(usize, usize)] = &[
+    (RTC_BASE, 0x1000),
     (0x10001000, 0x1000),
 ];
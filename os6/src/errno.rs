@@ -0,0 +1,72 @@
+//! Error numbers of the kernel
+//!
+//! Every `sys_` function returns a [`SysResult`], [`crate::syscall::syscall()`]
+//! hands an error to userspace as the negated [`Errno`] value.
+
+/// Error numbers, the values are the same as in Linux unless noted
+#[allow(unused)]
+#[repr(isize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Errno {
+    /// Operation not permitted
+    EPERM = 1,
+    /// No such file or directory
+    ENOENT = 2,
+    /// No such process
+    ESRCH = 3,
+    /// I/O error
+    EIO = 5,
+    /// Argument list too long
+    E2BIG = 7,
+    /// Exec format error
+    ENOEXEC = 8,
+    /// Bad file descriptor
+    EBADF = 9,
+    /// No child processes
+    ECHILD = 10,
+    /// Try again
+    EAGAIN = 11,
+    /// Out of memory
+    ENOMEM = 12,
+    /// Permission denied
+    EACCES = 13,
+    /// Bad address
+    EFAULT = 14,
+    /// Device or resource busy
+    EBUSY = 16,
+    /// File exists
+    EEXIST = 17,
+    /// Not a directory
+    ENOTDIR = 20,
+    /// Is a directory
+    EISDIR = 21,
+    /// Invalid argument
+    EINVAL = 22,
+    /// Too many open files
+    EMFILE = 24,
+    /// File too large
+    EFBIG = 27,
+    /// No space left on device
+    ENOSPC = 28,
+    /// Illegal seek
+    ESPIPE = 29,
+    /// Broken pipe
+    EPIPE = 32,
+    /// Result too large
+    ERANGE = 34,
+    /// Resource deadlock would occur
+    EDEADLK = 35,
+    /// File name too long
+    ENAMETOOLONG = 36,
+    /// Function not implemented
+    ENOSYS = 38,
+    /// Directory not empty
+    ENOTEMPTY = 39,
+    /// Too many symbolic links encountered
+    ELOOP = 40,
+    /// A deadlock is detected, not a Linux errno
+    EDEADLOCKED = 0xDEAD,
+}
+
+/// Result of a syscall, the value is returned to userspace on success
+pub type SysResult<T = usize> = Result<T, Errno>;
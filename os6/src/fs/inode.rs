@@ -1,18 +1,29 @@
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::any::Any;
 
 use bitflags::*;
-use easy_fs::{DiskInodeType, EasyFileSystem, Inode};
+use easy_fs::{DiskInodeType, DIRENT_SZ, EasyFileSystem, Inode, MAX_FILE_SIZE, NAME_LENGTH_LIMIT};
 use lazy_static::*;
 
 use crate::drivers::BLOCK_DEVICE;
-use crate::fs::{Stat, StatMode};
+use crate::errno::{Errno, SysResult};
+use crate::fs::{Stat, SEEK_CUR, SEEK_END, SEEK_SET};
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
 
 use super::File;
 
+/// Size of the fixed part of a `linux_dirent64`: d_ino, d_off, d_reclen and d_type
+const DIRENT64_HEADER_SZ: usize = 19;
+/// `d_type` of a directory
+const DT_DIR: u8 = 4;
+/// `d_type` of a regular file
+const DT_REG: u8 = 8;
+/// `d_type` of a symbolic link
+const DT_LNK: u8 = 10;
+
 /// A wrapper around a filesystem inode
 /// to implement File trait atop
 pub struct OSInode {
@@ -45,23 +56,65 @@ impl OSInode {
             },
         }
     }
-    /// Read all data inside a inode into vector
+    /// Read all data inside a inode into vector, the offset is not changed
     pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.exclusive_access();
+        let inner = self.inner.exclusive_access();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
+        let mut offset = 0;
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
+            let len = inner.inode.read_at(offset, &mut buffer);
             if len == 0 {
                 break;
             }
-            inner.offset += len;
+            offset += len;
             v.extend_from_slice(&buffer[..len]);
         }
         v
     }
 }
 
+impl OSInodeInner {
+    /// Read from `offset` into `buf`, returns the number of bytes read
+    fn read_at(&self, mut offset: usize, mut buf: UserBuffer) -> usize {
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let read_size = self.inode.read_at(offset, *slice);
+            offset += read_size;
+            total_read_size += read_size;
+            if read_size < slice.len() {
+                break;
+            }
+        }
+        total_read_size
+    }
+    /// Write `buf` at `offset`, returns the number of bytes written.
+    ///
+    /// The write stops at [`MAX_FILE_SIZE`], EFBIG if nothing can be written there,
+    /// ENOSPC if nothing can be written because the device is full
+    fn write_at(&self, mut offset: usize, buf: UserBuffer) -> SysResult {
+        if offset >= MAX_FILE_SIZE && buf.len() > 0 {
+            return Err(Errno::EFBIG);
+        }
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            let len = slice.len().min(MAX_FILE_SIZE - offset);
+            match self.inode.write_at(offset, &slice[..len]) {
+                Some(write_size) => {
+                    offset += write_size;
+                    total_write_size += write_size;
+                }
+                None if total_write_size == 0 => return Err(Errno::ENOSPC),
+                None => break,
+            }
+            if len < slice.len() {
+                break;
+            }
+        }
+        Ok(total_write_size)
+    }
+}
+
 lazy_static! {
     /// The root of all inodes, or '/' in short
     pub static ref ROOT_INODE: Arc<Inode> = {
@@ -89,6 +142,10 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        /// Fail if the path is not a directory
+        const DIRECTORY = 1 << 16;
+        /// Close the fd on exec
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -107,88 +164,303 @@ impl OpenFlags {
     }
 }
 
+/// Symbolic links followed in one path lookup at most
+const MAX_SYMLINKS: usize = 8;
+
+/// Find an inode by path, relative paths start from `cwd`.
+/// Symbolic links in the path are followed
+pub fn find_inode(cwd: &Arc<Inode>, path: &str) -> SysResult<Arc<Inode>> {
+    lookup(cwd, path, true, &mut 0)
+}
+
+/// Like [`find_inode`], but a symbolic link at the end of `path` is not followed
+pub fn find_link(cwd: &Arc<Inode>, path: &str) -> SysResult<Arc<Inode>> {
+    lookup(cwd, path, false, &mut 0)
+}
+
+/// Walk `path` from `cwd`, `follow` tells whether to follow a symbolic link
+/// as the last component. `links` counts the links followed so far, ELOOP beyond [`MAX_SYMLINKS`]
+fn lookup(cwd: &Arc<Inode>, path: &str, follow: bool, links: &mut usize) -> SysResult<Arc<Inode>> {
+    let mut inode = if path.starts_with('/') {
+        ROOT_INODE.clone()
+    } else {
+        cwd.clone()
+    };
+    let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+    while let Some(name) = names.next() {
+        if !inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        let next = inode.find(name).ok_or(Errno::ENOENT)?;
+        if next.is_symlink() && (follow || names.peek().is_some()) {
+            *links += 1;
+            if *links > MAX_SYMLINKS {
+                return Err(Errno::ELOOP);
+            }
+            let target = next.read_link().ok_or(Errno::EIO)?;
+            // a relative target starts from the directory holding the link
+            inode = lookup(&inode, &target, true, links)?;
+        } else {
+            inode = next;
+        }
+    }
+    Ok(inode)
+}
+
+/// Find the directory holding the last component of `path`,
+/// returns the directory inode and the last component
+fn find_parent<'a>(cwd: &Arc<Inode>, path: &'a str) -> SysResult<(Arc<Inode>, &'a str)> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((dir, name)) => (dir, name),
+        None => ("", path),
+    };
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Errno::EINVAL);
+    }
+    if name.len() > NAME_LENGTH_LIMIT {
+        return Err(Errno::ENAMETOOLONG);
+    }
+    let parent = find_inode(cwd, dir)?;
+    if !parent.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    Ok((parent, name))
+}
+
+/// Get the absolute path of a directory by walking up its `..` entries
+pub fn dir_path(dir: &Arc<Inode>) -> Option<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut inode = dir.clone();
+    while inode.inode_id() != ROOT_INODE.inode_id() {
+        let parent = inode.find("..")?;
+        names.push(parent.find_name(inode.inode_id())?);
+        inode = parent;
+    }
+    if names.is_empty() {
+        return Some(String::from("/"));
+    }
+    let mut path = String::new();
+    for name in names.iter().rev() {
+        path.push('/');
+        path.push_str(name);
+    }
+    Some(path)
+}
+
 /// Open a file by path
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+pub fn open_file(cwd: &Arc<Inode>, path: &str, flags: OpenFlags) -> SysResult<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+    // a directory cannot be created by open
+    if flags.contains(OpenFlags::CREATE | OpenFlags::DIRECTORY) {
+        return Err(Errno::EINVAL);
+    }
+    let inode = if flags.contains(OpenFlags::CREATE) {
+        let (parent, name) = find_parent(cwd, path)?;
+        if let Some(mut inode) = parent.find(name) {
+            if inode.is_symlink() {
+                // the target of the link is opened, it is not created if missing
+                inode = find_inode(&parent, name)?;
+            }
+            if inode.is_dir() {
+                return Err(Errno::EISDIR);
+            }
             // clear size
             inode.clear();
-            Some(Arc::new(OSInode::new(
-                readable,
-                writable,
-                inode,
-            )))
+            inode
         } else {
             // create file
-            ROOT_INODE.create(name)
-                .map(|inode| {
-                    Arc::new(OSInode::new(
-                        readable,
-                        writable,
-                        inode,
-                    ))
-                })
+            parent.create(name).ok_or(Errno::ENOSPC)?
         }
     } else {
-        ROOT_INODE.find(name)
-            .map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(OSInode::new(
-                    readable,
-                    writable,
-                    inode,
-                ))
-            })
+        let inode = find_inode(cwd, path)?;
+        if flags.contains(OpenFlags::DIRECTORY) && !inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        if inode.is_dir() {
+            // directories can only be opened for reading
+            if writable {
+                return Err(Errno::EISDIR);
+            }
+        } else if flags.contains(OpenFlags::TRUNC) {
+            inode.clear();
+        }
+        inode
+    };
+    Ok(Arc::new(OSInode::new(
+        readable,
+        writable,
+        inode,
+    )))
+}
+
+/// Create a directory by path
+pub fn mkdirat(cwd: &Arc<Inode>, path: &str) -> SysResult<()> {
+    let (parent, name) = find_parent(cwd, path)?;
+    if parent.find(name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    parent.create_dir(name).map(|_| ()).ok_or(Errno::ENOSPC)
+}
+
+pub fn linkat(cwd: &Arc<Inode>, old_path: &str, new_path: &str) -> SysResult<()> {
+    // like Linux, a hard link to a symbolic link links the symbolic link itself
+    let inode = find_link(cwd, old_path)?;
+    // hard links to directories would make the tree cyclic
+    if inode.is_dir() {
+        return Err(Errno::EPERM);
+    }
+    let (parent, name) = find_parent(cwd, new_path)?;
+    if parent.find(name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    parent.link(name, &inode).ok_or(Errno::ENOSPC)
+}
+
+pub fn unlinkat(cwd: &Arc<Inode>, path: &str) -> SysResult<()> {
+    let (parent, name) = find_parent(cwd, path)?;
+    if parent.find(name).ok_or(Errno::ENOENT)?.is_dir() {
+        return Err(Errno::EISDIR);
     }
+    parent.unlink(name).ok_or(Errno::ENOENT)
 }
 
-pub fn linkat(old_name: &str, new_name: &str) -> Option<()> {
-    ROOT_INODE.link(old_name, new_name)
+/// Create a symbolic link at `path` pointing to `target`, which is not checked
+pub fn symlinkat(cwd: &Arc<Inode>, target: &str, path: &str) -> SysResult<()> {
+    if target.is_empty() {
+        return Err(Errno::ENOENT);
+    }
+    let (parent, name) = find_parent(cwd, path)?;
+    if parent.find(name).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    parent.symlink(name, target).map(|_| ()).ok_or(Errno::ENOSPC)
 }
 
-pub fn unlinkat(name: &str) -> Option<()> {
-    ROOT_INODE.unlink(name)
+/// Get the target of the symbolic link at `path`, EINVAL if it is not a symbolic link
+pub fn readlinkat(cwd: &Arc<Inode>, path: &str) -> SysResult<String> {
+    find_link(cwd, path)?.read_link().ok_or(Errno::EINVAL)
+}
+
+/// Rename `old_path` to `new_path`, an existing `new_path` is replaced
+/// if it is a file or an empty directory of the same kind, EEXIST with `noreplace`
+pub fn renameat(cwd: &Arc<Inode>, old_path: &str, new_path: &str, noreplace: bool) -> SysResult<()> {
+    let (old_parent, old_name) = find_parent(cwd, old_path)?;
+    let inode = old_parent.find(old_name).ok_or(Errno::ENOENT)?;
+    let (new_parent, new_name) = find_parent(cwd, new_path)?;
+    if inode.is_dir() {
+        // a directory cannot be moved under itself
+        let mut dir = new_parent.clone();
+        while dir.inode_id() != ROOT_INODE.inode_id() {
+            if dir.inode_id() == inode.inode_id() {
+                return Err(Errno::EINVAL);
+            }
+            dir = dir.find("..").ok_or(Errno::ENOENT)?;
+        }
+    }
+    if let Some(target) = new_parent.find(new_name) {
+        if noreplace {
+            return Err(Errno::EEXIST);
+        }
+        if target.inode_id() != inode.inode_id() {
+            match (inode.is_dir(), target.is_dir()) {
+                (false, true) => return Err(Errno::EISDIR),
+                (true, false) => return Err(Errno::ENOTDIR),
+                // only `.` and `..` are left in an empty directory
+                (true, true) if target.ls().len() > 2 => return Err(Errno::ENOTEMPTY),
+                _ => {}
+            }
+        }
+    }
+    // the names are checked above, the new directory may be full
+    old_parent.rename(old_name, &new_parent, new_name).ok_or(Errno::ENOSPC)
 }
 
 impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, buf: UserBuffer) -> usize {
         let mut inner = self.inner.exclusive_access();
-        let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
-            if read_size == 0 {
-                break;
-            }
-            inner.offset += read_size;
-            total_read_size += read_size;
-        }
-        total_read_size
+        let read_size = inner.read_at(inner.offset, buf);
+        inner.offset += read_size;
+        read_size
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> SysResult {
         let mut inner = self.inner.exclusive_access();
-        let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
-            inner.offset += write_size;
-            total_write_size += write_size;
+        let write_size = inner.write_at(inner.offset, buf)?;
+        inner.offset += write_size;
+        Ok(write_size)
+    }
+    fn read_at(&self, offset: usize, buf: UserBuffer) -> SysResult {
+        Ok(self.inner.exclusive_access().read_at(offset, buf))
+    }
+    fn write_at(&self, offset: usize, buf: UserBuffer) -> SysResult {
+        self.inner.exclusive_access().write_at(offset, buf)
+    }
+    /// The offset may be beyond the end of the file
+    fn seek(&self, offset: isize, whence: usize) -> SysResult {
+        let mut inner = self.inner.exclusive_access();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => inner.offset,
+            SEEK_END => inner.inode.size(),
+            _ => return Err(Errno::EINVAL),
+        };
+        let new_offset = (base as isize).checked_add(offset).ok_or(Errno::EINVAL)?;
+        if new_offset < 0 {
+            return Err(Errno::EINVAL);
         }
-        total_write_size
+        inner.offset = new_offset as usize;
+        Ok(inner.offset)
     }
     fn stat(&self) -> Stat {
         let inner = self.inner.exclusive_access();
-        let stat = inner.inode.stat();
-        let sm = if stat.2 == DiskInodeType::File {
-            StatMode::FILE
-        } else {
-            StatMode::DIR
-        };
-        Stat::new(stat.0 as usize, sm, stat.1)
+        Stat::new(&inner.inode.stat())
+    }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(self.inner.exclusive_access().inode.clone())
+    }
+    /// The offset of a directory is the index of its next entry times [`DIRENT_SZ`]
+    fn getdents(&self, buf: UserBuffer) -> SysResult {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        let start = inner.offset / DIRENT_SZ;
+        let mut records: Vec<u8> = Vec::new();
+        let entries = inner.inode.read_dir(start);
+        let remaining = entries.len();
+        let mut count = 0;
+        for (name, inode_id, type_) in entries {
+            // name and its '\0' after the header, aligned to 8 bytes
+            let reclen = (DIRENT64_HEADER_SZ + name.len() + 1 + 7) & !7;
+            if records.len() + reclen > buf.len() {
+                break;
+            }
+            let d_type = match type_ {
+                DiskInodeType::File => DT_REG,
+                DiskInodeType::Directory => DT_DIR,
+                DiskInodeType::Symlink => DT_LNK,
+            };
+            let d_off = (start + count + 1) * DIRENT_SZ;
+            records.extend_from_slice(&(inode_id as u64).to_ne_bytes());
+            records.extend_from_slice(&(d_off as i64).to_ne_bytes());
+            records.extend_from_slice(&(reclen as u16).to_ne_bytes());
+            records.push(d_type);
+            records.extend_from_slice(name.as_bytes());
+            records.resize(records.len() + reclen - DIRENT64_HEADER_SZ - name.len(), 0);
+            count += 1;
+        }
+        // the buffer cannot hold the next entry
+        if count == 0 && remaining > 0 {
+            return Err(Errno::EINVAL);
+        }
+        for (byte_ref, byte) in buf.into_iter().zip(records.iter()) {
+            unsafe { *byte_ref = *byte; }
+        }
+        inner.offset = (start + count) * DIRENT_SZ;
+        Ok(records.len())
     }
 }
 
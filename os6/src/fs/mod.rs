@@ -1,29 +1,85 @@
 use alloc::sync::Arc;
 use core::any::{Any, TypeId};
 
-pub use inode::{linkat, list_apps, open_file, OpenFlags, OSInode, unlinkat};
+pub use inode::{dir_path, find_inode, find_link, linkat, list_apps, mkdirat, open_file, OpenFlags, OSInode, readlinkat, renameat, ROOT_INODE, symlinkat, unlinkat};
 pub use pipe::{make_pipe, Pipe};
-pub use stdio::{Stdin, Stdout};
+pub use stdio::{stdin_poll, Stdin, Stdout};
 
+use easy_fs::{DiskInodeType, Inode, InodeStat};
+
+use crate::errno::{Errno, SysResult};
 use crate::mm::UserBuffer;
 
 mod stdio;
 mod inode;
 mod pipe;
 
+/// `whence` of [`File::seek`]: from the start of the file
+pub const SEEK_SET: usize = 0;
+/// from the current offset
+pub const SEEK_CUR: usize = 1;
+/// from the end of the file
+pub const SEEK_END: usize = 2;
+
 /// The common abstraction of all IO resources
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
-    fn write(&self, buf: UserBuffer) -> usize;
+    fn write(&self, buf: UserBuffer) -> SysResult;
     fn stat(&self) -> Stat;
+    /// The filesystem inode behind the file, if any
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
+    /// Read at `offset` without using or changing the file offset,
+    /// ESPIPE if the file is not seekable
+    fn read_at(&self, _offset: usize, _buf: UserBuffer) -> SysResult {
+        Err(Errno::ESPIPE)
+    }
+    /// Write at `offset` without using or changing the file offset,
+    /// ESPIPE if the file is not seekable
+    fn write_at(&self, _offset: usize, _buf: UserBuffer) -> SysResult {
+        Err(Errno::ESPIPE)
+    }
+    /// Move the file offset by `offset` from the position `whence`, returns the new offset.
+    /// ESPIPE if the file is not seekable
+    fn seek(&self, _offset: isize, _whence: usize) -> SysResult {
+        Err(Errno::ESPIPE)
+    }
+    /// Capacity of a pipe, EBADF if the file is not a pipe
+    fn pipe_size(&self) -> SysResult {
+        Err(Errno::EBADF)
+    }
+    /// Change the capacity of a pipe, returns the actual new capacity
+    fn set_pipe_size(&self, _size: usize) -> SysResult {
+        Err(Errno::EBADF)
+    }
+    /// Fill `buf` with `linux_dirent64` records of the next directory entries,
+    /// returns the number of bytes filled, 0 at the end of the directory.
+    /// ENOTDIR if the file is not a directory
+    fn getdents(&self, _buf: UserBuffer) -> SysResult {
+        Err(Errno::ENOTDIR)
+    }
 }
 
+/// An entry of the fd table of a process
+#[derive(Clone)]
+pub struct FileDescriptor {
+    pub file: Arc<dyn File + Send + Sync>,
+    /// The fd is closed when the process calls exec
+    pub cloexec: bool,
+}
+
+impl FileDescriptor {
+    pub fn new(file: Arc<dyn File + Send + Sync>, cloexec: bool) -> Self {
+        Self { file, cloexec }
+    }
+}
 
 /// The stat of a inode
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Stat {
     /// ID of device containing file
     pub dev: u64,
@@ -33,30 +89,76 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// user ID of owner
+    pub uid: u32,
+    /// group ID of owner
+    pub gid: u32,
+    /// total size in bytes
+    pub size: u64,
+    /// time of last access in seconds
+    pub atime: u64,
+    /// time of last modification
+    pub mtime: u64,
+    /// time of last status change
+    pub ctime: u64,
+    /// permission bits, `mode` only holds the file type
+    pub perm: u32,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u32; 3],
 }
 
 impl Stat {
-    pub fn new(inode: usize, mode: StatMode, nlink: u32) -> Self {
+    /// Fill in the stat of a filesystem inode
+    pub fn new(stat: &InodeStat) -> Self {
+        let mode = match stat.type_ {
+            DiskInodeType::File => StatMode::FILE,
+            DiskInodeType::Directory => StatMode::DIR,
+            DiskInodeType::Symlink => StatMode::LNK,
+        };
         Stat {
             dev: 0,
-            ino: inode as u64,
+            ino: stat.inode_id as u64,
             mode,
-            nlink,
-            pad: [0; 7],
+            nlink: stat.nlink,
+            uid: stat.uid,
+            gid: stat.gid,
+            size: stat.size as u64,
+            atime: stat.atime as u64,
+            mtime: stat.mtime as u64,
+            ctime: stat.ctime as u64,
+            perm: stat.mode as u32,
+            pad: [0; 3],
+        }
+    }
+    /// Stat of a file without an inode, such as stdio and pipes
+    pub fn null() -> Self {
+        Stat {
+            dev: 0,
+            ino: 0,
+            mode: StatMode::NULL,
+            nlink: 1,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            perm: 0,
+            pad: [0; 3],
         }
     }
 }
 bitflags! {
     /// The mode of a inode
-    /// whether a directory or a file
+    /// whether a directory, a file or a symbolic link
     pub struct StatMode: u32 {
         const NULL  = 0;
         /// directory
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// symbolic link
+        const LNK   = 0o120000;
     }
 }
 
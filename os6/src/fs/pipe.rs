@@ -1,12 +1,23 @@
-use alloc::sync::{Arc, Weak};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
-use crate::fs::{Stat, StatMode};
+use crate::config::PAGE_SIZE;
+use crate::errno::{Errno, SysResult};
+use crate::fs::Stat;
 use crate::mm::UserBuffer;
 use crate::sync::UPSafeCell;
-use crate::task::suspend_current_and_run_next;
+use crate::task::{block_current_and_run_next, current_killed, current_process, SignalFlags, WaitQueue};
 
 use super::File;
 
+/// Writes of at most PIPE_BUF bytes are not interleaved with other writes
+pub const PIPE_BUF: usize = PAGE_SIZE;
+/// Capacity of a new pipe
+const PIPE_DEFAULT_SIZE: usize = PAGE_SIZE;
+/// Largest capacity set by F_SETPIPE_SZ
+const PIPE_MAX_SIZE: usize = 16 * PAGE_SIZE;
+
 /// One end of a pipe
 pub struct Pipe {
     readable: bool,
@@ -33,80 +44,75 @@ impl Pipe {
     }
 }
 
-const RING_BUFFER_SIZE: usize = 32;
-
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    FULL,
-    EMPTY,
-    NORMAL,
-}
-
 /// The underlying ring buffer of a pipe
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Vec<u8>,
     head: usize,
-    tail: usize,
-    status: RingBufferStatus,
-    write_end: Option<Weak<Pipe>>,
+    /// Number of bytes in the buffer
+    len: usize,
+    /// The read end has been dropped, an end is shared by `Arc` across dup and fork
+    read_closed: bool,
+    /// The write end has been dropped
+    write_closed: bool,
+    /// Readers waiting for data or the write ends to be closed
+    read_wait: WaitQueue,
+    /// Writers waiting for space or the read ends to be closed
+    write_wait: WaitQueue,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; PIPE_DEFAULT_SIZE],
             head: 0,
-            tail: 0,
-            status: RingBufferStatus::EMPTY,
-            write_end: None,
+            len: 0,
+            read_closed: false,
+            write_closed: false,
+            read_wait: WaitQueue::new(),
+            write_wait: WaitQueue::new(),
         }
     }
-    /// Set the write end bound to this buffer
-    pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
-        self.write_end = Some(Arc::downgrade(write_end));
+    pub fn capacity(&self) -> usize {
+        self.arr.len()
     }
     /// Write into the buffer
     pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::NORMAL;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::FULL;
-        }
+        let tail = (self.head + self.len) % self.capacity();
+        self.arr[tail] = byte;
+        self.len += 1;
     }
     /// Read from the buffer
     pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::NORMAL;
         let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::EMPTY;
-        }
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
         c
     }
     /// Get the length of remaining data in the buffer
     pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::EMPTY {
-            0
-        } else {
-            if self.tail > self.head {
-                self.tail - self.head
-            } else {
-                self.tail + RING_BUFFER_SIZE - self.head
-            }
-        }
+        self.len
     }
     /// Get the length of remaining space in the buffer
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::FULL {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read()
-        }
+        self.capacity() - self.len
     }
     /// Check if all write ends bounded to this buffer are closed
     pub fn all_write_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+        self.write_closed
+    }
+    /// Check if all read ends bounded to this buffer are closed
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_closed
+    }
+    /// Change the capacity to `size` bytes, keeping the data
+    pub fn resize(&mut self, size: usize) {
+        let mut arr = vec![0; size];
+        for byte in arr.iter_mut().take(self.len) {
+            *byte = self.arr[self.head];
+            self.head = (self.head + 1) % self.capacity();
+        }
+        self.arr = arr;
+        self.head = 0;
     }
 }
 
@@ -122,7 +128,6 @@ pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
     let write_end = Arc::new(
         Pipe::write_end_with_buffer(buffer.clone())
     );
-    buffer.exclusive_access().set_write_end(&write_end);
     (read_end, write_end)
 }
 
@@ -140,8 +145,14 @@ impl File for Pipe {
                 if ring_buffer.all_write_ends_closed() {
                     return read_size;
                 }
+                // sleep until a writer makes room for us
+                ring_buffer.read_wait.add_current();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                block_current_and_run_next();
+                if current_killed() {
+                    self.buffer.exclusive_access().read_wait.remove_current();
+                    return read_size;
+                }
                 continue;
             }
             // read at most loop_read bytes
@@ -150,21 +161,37 @@ impl File for Pipe {
                     unsafe { *byte_ref = ring_buffer.read_byte(); }
                     read_size += 1;
                 } else {
+                    ring_buffer.write_wait.wake_all();
                     return read_size;
                 }
             }
+            ring_buffer.write_wait.wake_all();
         }
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: UserBuffer) -> SysResult {
         assert_eq!(self.writable(), true);
+        let total = buf.len();
         let mut buf_iter = buf.into_iter();
         let mut write_size = 0usize;
         loop {
             let mut ring_buffer = self.buffer.exclusive_access();
+            // nobody will read the rest
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                current_process().inner_exclusive_access().signals.insert(SignalFlags::SIGPIPE);
+                return if write_size == 0 { Err(Errno::EPIPE) } else { Ok(write_size) };
+            }
             let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
+            // a small write goes into the buffer at once
+            let atomic = total <= PIPE_BUF && loop_write < total;
+            if loop_write == 0 || atomic {
+                ring_buffer.write_wait.add_current();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                block_current_and_run_next();
+                if current_killed() {
+                    self.buffer.exclusive_access().write_wait.remove_current();
+                    return Ok(write_size);
+                }
                 continue;
             }
             // write at most loop_write bytes
@@ -173,13 +200,51 @@ impl File for Pipe {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     write_size += 1;
                 } else {
-                    return write_size;
+                    ring_buffer.read_wait.wake_all();
+                    return Ok(write_size);
                 }
             }
+            ring_buffer.read_wait.wake_all();
         }
     }
 
     fn stat(&self) -> Stat {
-        Stat::new(0, StatMode::NULL, 1)
+        Stat::null()
+    }
+
+    fn pipe_size(&self) -> SysResult {
+        Ok(self.buffer.exclusive_access().capacity())
+    }
+
+    /// The size is rounded up to pages, at least PIPE_BUF bytes.
+    /// EBUSY if the data does not fit in the new size
+    fn set_pipe_size(&self, size: usize) -> SysResult {
+        if size > PIPE_MAX_SIZE {
+            return Err(Errno::EPERM);
+        }
+        let size = ((size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE).max(PIPE_BUF);
+        let mut ring_buffer = self.buffer.exclusive_access();
+        if ring_buffer.available_read() > size {
+            return Err(Errno::EBUSY);
+        }
+        ring_buffer.resize(size);
+        // there may be room for a blocked writer now
+        ring_buffer.write_wait.wake_all();
+        Ok(size)
+    }
+}
+
+impl Drop for Pipe {
+    /// Wake up the threads waiting for the other end, they see that this end is closed
+    fn drop(&mut self) {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        if self.readable {
+            ring_buffer.read_closed = true;
+            ring_buffer.write_wait.wake_all();
+        }
+        if self.writable {
+            ring_buffer.write_closed = true;
+            ring_buffer.read_wait.wake_all();
+        }
     }
 }
@@ -1,10 +1,52 @@
-use crate::fs::{Stat, StatMode};
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+use crate::errno::SysResult;
+use crate::fs::Stat;
 use crate::mm::UserBuffer;
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, WaitQueue};
 
 use super::File;
 
+/// Characters read from the console ahead and the threads waiting for them
+struct StdinBuffer {
+    chars: VecDeque<u8>,
+    wait_queue: WaitQueue,
+}
+
+lazy_static! {
+    static ref STDIN_BUFFER: UPSafeCell<StdinBuffer> = unsafe {
+        UPSafeCell::new(StdinBuffer {
+            chars: VecDeque::new(),
+            wait_queue: WaitQueue::new(),
+        })
+    };
+}
+
+/// Poll the console for the threads blocked on stdin, the console does not interrupt.
+///
+/// Called on timer interrupts and when there is no task to run
+pub fn stdin_poll() {
+    let mut stdin = STDIN_BUFFER.exclusive_access();
+    if stdin.wait_queue.is_empty() {
+        return;
+    }
+    loop {
+        let c = console_getchar();
+        if c == 0 {
+            break;
+        }
+        stdin.chars.push_back(c as u8);
+    }
+    if !stdin.chars.is_empty() {
+        stdin.wait_queue.wake_all();
+    }
+}
+
 /// The standard input
 pub struct Stdin;
 
@@ -14,28 +56,48 @@ pub struct Stdout;
 impl File for Stdin {
     fn readable(&self) -> bool { true }
     fn writable(&self) -> bool { false }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+    /// Read the characters at hand, up to the length of `user_buf`,
+    /// block only if there is none
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        if user_buf.len() == 0 {
+            return 0;
+        }
+        let mut stdin = loop {
+            let mut stdin = STDIN_BUFFER.exclusive_access();
+            loop {
+                let c = console_getchar();
+                if c == 0 {
+                    break;
+                }
+                stdin.chars.push_back(c as u8);
+            }
+            if !stdin.chars.is_empty() {
+                break stdin;
+            }
+            // sleep until stdin_poll gets a character
+            stdin.wait_queue.add_current();
+            drop(stdin);
+            block_current_and_run_next();
+            if current_killed() {
+                STDIN_BUFFER.exclusive_access().wait_queue.remove_current();
+                return 0;
+            }
+        };
+        let mut read_size = 0usize;
+        for byte_ref in user_buf.into_iter() {
+            match stdin.chars.pop_front() {
+                Some(ch) => unsafe { *byte_ref = ch; },
+                None => break,
             }
+            read_size += 1;
         }
-        let ch = c as u8;
-        unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch); }
-        1
+        read_size
     }
-    fn write(&self, _user_buf: UserBuffer) -> usize {
+    fn write(&self, _user_buf: UserBuffer) -> SysResult {
         panic!("Cannot write to stdin!");
     }
     fn stat(&self) -> Stat {
-        Stat::new(0, StatMode::NULL, 1)
+        Stat::null()
     }
 }
 
@@ -45,13 +107,16 @@ impl File for Stdout {
     fn read(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot read from stdout!");
     }
-    fn write(&self, user_buf: UserBuffer) -> usize {
+    fn write(&self, user_buf: UserBuffer) -> SysResult {
+        // join the pages first, a character may be split between two of them
+        let mut bytes: Vec<u8> = Vec::with_capacity(user_buf.len());
         for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+            bytes.extend_from_slice(buffer);
         }
-        user_buf.len()
+        print!("{}", String::from_utf8_lossy(&bytes));
+        Ok(bytes.len())
     }
     fn stat(&self) -> Stat {
-        Stat::new(0, StatMode::NULL, 1)
+        Stat::null()
     }
 }
@@ -26,13 +26,14 @@ extern crate bitflags;
 #[macro_use]
 extern crate log;
 
-use easy_fs::{hello_world_in_easy_fs, set_console_putchar};
+use easy_fs::{hello_world_in_easy_fs, set_clock, set_console_putchar};
 
 use crate::sbi::console_putchar;
 
 #[macro_use]
 mod console;
 mod config;
+mod errno;
 mod lang_items;
 mod logging;
 mod mm;
@@ -66,6 +67,7 @@ pub fn rust_main() -> ! {
     logging::init();
     println!("[kernel] Hello, world!");
     set_console_putchar(console_putchar as *const ());
+    set_clock(timer::get_time_sec as *const ());
     hello_world_in_easy_fs();
     mm::init();
     mm::remap_test();
@@ -55,6 +55,10 @@ impl StackFrameAllocator {
         self.end = r.0;
         info!("last {} Physical Frames.", self.end - self.current);
     }
+    /// number of frames that can still be allocated
+    pub fn remaining(&self) -> usize {
+        self.end - self.current + self.recycled.len()
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
@@ -111,6 +115,11 @@ pub fn frame_alloc() -> Option<FrameTracker> {
         .map(FrameTracker::new)
 }
 
+/// number of free frames
+pub fn frame_remaining() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().remaining()
+}
+
 /// deallocate a frame
 pub fn frame_dealloc(ppn: PhysPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
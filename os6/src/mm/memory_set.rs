@@ -4,16 +4,20 @@ use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
+use easy_fs::Inode;
 use lazy_static::*;
 use riscv::register::satp;
 
-use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use crate::config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE};
+use crate::errno::{Errno, SysResult};
 use crate::sync::UPSafeCell;
 
-use super::{frame_alloc, FrameTracker};
-use super::{PageTable, PageTableEntry, PTEFlags};
+use super::{frame_alloc, frame_remaining, FrameTracker};
+use super::{PageTable, PageTableEntry, PTEFlags, UserBuffer};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
+use super::swap::{swap_out, SwapTracker};
+use crate::task::swap_out_any;
 
 extern "C" {
     fn stext();
@@ -38,10 +42,15 @@ pub fn kernel_token() -> usize {
     KERNEL_SPACE.exclusive_access().token()
 }
 
+/// a page fault takes one data frame and at most two page table frames
+const FAULT_FRAMES: usize = 3;
+
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// the page the clock of page replacement stopped at last time
+    clock_hand: VirtPageNum,
 }
 
 impl MemorySet {
@@ -49,42 +58,149 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            clock_hand: VirtPageNum(0),
         }
     }
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
     /// Assume that no conflicts.
+    /// ENOMEM if there are not enough frames, then nothing is mapped
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
         end_va: VirtAddr,
         permission: MapPermission,
-    ) {
+    ) -> SysResult<()> {
         self.push(
             MapArea::new(start_va, end_va, MapType::Framed, permission),
             None,
-        );
+        )
+    }
+    /// Reserve a framed area without allocating frames,
+    /// pages get their frames on first access, see `handle_lazy_fault`
+    pub fn insert_lazy_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        self.areas.push(MapArea::new(start_va, end_va, MapType::Framed, permission));
     }
+    /// Reserve an area whose pages are loaded from `inode` starting at `offset` on first access,
+    /// with `shared` stores are written back to the file when the pages are unmapped
+    pub fn insert_file_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        inode: Arc<Inode>,
+        offset: usize,
+        shared: bool,
+    ) {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        map_area.file = Some(MapFile {
+            inode,
+            offset,
+            shared,
+        });
+        self.areas.push(map_area);
+    }
+    /// Move the end of the area starting at `start_va` to `new_end`, pages above the new end are unmapped,
+    /// returns false if there is no such area or the grown part overlaps another area
+    pub fn resize_area(&mut self, start_va: VirtAddr, new_end: VirtAddr) -> bool {
+        let start: VirtPageNum = start_va.floor();
+        let new_end: VirtPageNum = new_end.ceil();
+        let index = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start)
+        {
+            Some(index) => index,
+            None => return false,
+        };
+        let end = self.areas[index].vpn_range.get_end();
+        if new_end > end && self.is_mapped(end.into(), new_end.into()) {
+            return false;
+        }
+        self.areas[index].resize(&mut self.page_table, new_end);
+        true
+    }
+    /// Unmap user pages in [start_va, end_va), splitting areas that are partly inside,
+    /// returns false if some page in the range does not belong to a user area
     pub fn remove_framed_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
         let start: VirtPageNum = start_va.floor();
         let end: VirtPageNum = end_va.ceil();
-        for (index, area) in self.areas.iter_mut().enumerate() {
-            if area.data_frames.contains_key(&start) {
-                let mut start = start;
-                start.step();
-                while start < end {
-                    //unmap不存在的空间
-                    if !area.data_frames.contains_key(&start) {
-                        return false;
-                    }
-                }
+        if !self.is_user_range(start, end) {
+            return false;
+        }
+        self.split_areas_at(start);
+        self.split_areas_at(end);
+        let mut index = 0;
+        while index < self.areas.len() {
+            let area = &mut self.areas[index];
+            if start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end {
                 area.unmap(&mut self.page_table);
                 self.areas.remove(index);
-                return true;
+            } else {
+                index += 1;
+            }
+        }
+        true
+    }
+    /// Change the permission of user pages in [start_va, end_va) to `permission`,
+    /// splitting areas that are partly inside,
+    /// returns false if some page in the range does not belong to a user area
+    pub fn protect_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> bool {
+        let start: VirtPageNum = start_va.floor();
+        let end: VirtPageNum = end_va.ceil();
+        if !self.is_user_range(start, end) {
+            return false;
+        }
+        self.split_areas_at(start);
+        self.split_areas_at(end);
+        for area in self.areas.iter_mut() {
+            if start <= area.vpn_range.get_start() && area.vpn_range.get_end() <= end {
+                area.set_perm(&mut self.page_table, permission | MapPermission::U);
             }
         }
-        return false;
+        true
+    }
+    /// Whether every page in [start, end) belongs to a framed user area
+    fn is_user_range(&self, start: VirtPageNum, end: VirtPageNum) -> bool {
+        if start >= end {
+            return false;
+        }
+        // areas never overlap, so counting the covered pages is enough
+        let covered: usize = self
+            .areas
+            .iter()
+            .filter(|area| {
+                area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U)
+            })
+            .map(|area| {
+                let l = area.vpn_range.get_start().max(start);
+                let r = area.vpn_range.get_end().min(end);
+                r.0.saturating_sub(l.0)
+            })
+            .sum();
+        covered == end.0 - start.0
+    }
+    /// Split the area containing `vpn` in two so that no area crosses `vpn`
+    fn split_areas_at(&mut self, vpn: VirtPageNum) {
+        if let Some(index) = self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() < vpn && area.contains(vpn))
+        {
+            let tail = self.areas[index].split_off(vpn);
+            self.areas.insert(index + 1, tail);
+        }
     }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
@@ -97,26 +213,27 @@ impl MemorySet {
             self.areas.remove(idx);
         }
     }
-    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    /// Map the area and copy `data` into it,
+    /// ENOMEM if there are not enough frames, then nothing is mapped
+    fn push(&mut self, mut map_area: MapArea, data: Option<&[u8]>) -> SysResult<()> {
+        if let Err(err) = map_area.map(&mut self.page_table) {
+            map_area.unmap(&mut self.page_table);
+            return Err(err);
+        }
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        Ok(())
     }
+    /// Whether any page in [start_va, end_va) belongs to an area,
+    /// including pages reserved but not yet backed by frames
     pub fn is_mapped(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
         let start: VirtPageNum = start_va.floor();
         let end: VirtPageNum = end_va.ceil();
-        for area in &self.areas {
-            let mut start = start;
-            while start < end {
-                if area.data_frames.contains_key(&start) {
-                    return true;
-                }
-                start.step();
-            }
-        }
-        false
+        self.areas.iter().any(|area| {
+            area.vpn_range.get_start() < end && start < area.vpn_range.get_end()
+        })
     }
     /// Mention that trampoline is not collected by areas.
     fn map_trampoline(&mut self) {
@@ -148,7 +265,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
+        )
+        .unwrap();
         info!("mapping .rodata section");
         memory_set.push(
             MapArea::new(
@@ -158,7 +276,8 @@ impl MemorySet {
                 MapPermission::R,
             ),
             None,
-        );
+        )
+        .unwrap();
         info!("mapping .data section");
         memory_set.push(
             MapArea::new(
@@ -168,7 +287,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
         info!("mapping .bss section");
         memory_set.push(
             MapArea::new(
@@ -178,7 +298,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
         info!("mapping physical memory");
         memory_set.push(
             MapArea::new(
@@ -188,7 +309,8 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
         info!("mapping memory-mapped registers");
         for pair in MMIO {
             memory_set.push(
@@ -198,28 +320,44 @@ impl MemorySet {
                     MapType::Identical,
                     MapPermission::R | MapPermission::W,
                 ),
-                None);
+                None)
+                .unwrap();
         }
         memory_set
     }
-    /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
-        let mut memory_set = Self::new_bare();
-        // map trampoline
-        memory_set.map_trampoline();
+    /// Include sections in elf and trampoline and an empty heap,
+    /// also returns the bottom of the heap and entry point.
+    /// Returns ENOEXEC if `elf_data` is not a valid elf, ENOMEM if there are not enough frames.
+    pub fn from_elf(elf_data: &[u8]) -> SysResult<(Self, usize, usize)> {
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(|_| Errno::ENOEXEC)?;
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(Errno::ENOEXEC);
+        }
+        let mut memory_set = Self::new_bare();
+        // map trampoline
+        memory_set.map_trampoline();
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+            let ph = elf.program_header(i).map_err(|_| Errno::ENOEXEC)?;
+            if ph.get_type().map_err(|_| Errno::ENOEXEC)? == xmas_elf::program::Type::Load {
+                // the file part must lie in the elf and fit in the segment
+                let data_start = ph.offset() as usize;
+                let data_end = data_start
+                    .checked_add(ph.file_size() as usize)
+                    .filter(|&end| end <= elf.input.len())
+                    .ok_or(Errno::ENOEXEC)?;
+                if ph.file_size() > ph.mem_size() {
+                    return Err(Errno::ENOEXEC);
+                }
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+                let end_va: VirtAddr = (ph.virtual_addr() as usize)
+                    .checked_add(ph.mem_size() as usize)
+                    .ok_or(Errno::ENOEXEC)?
+                    .into();
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -232,53 +370,62 @@ impl MemorySet {
                     map_perm |= MapPermission::X;
                 }
                 let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-                max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                // the segments are not necessarily sorted by address
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.get_end());
+                memory_set.push(map_area, Some(&elf.input[data_start..data_end]))?;
             }
         }
-        // map user stack with U flags
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
+        let mut heap_bottom: usize = max_end_va.into();
         // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
+        heap_bottom += PAGE_SIZE;
+        // empty heap above the program, moved by sys_brk
+        memory_set.insert_lazy_area(
+            heap_bottom.into(),
+            heap_bottom.into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        // map TrapContext
-        memory_set.push(
-            MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-        (
+        // user stacks and trap contexts belong to threads, see `TaskUserRes`
+        Ok((
             memory_set,
-            user_stack_top,
+            heap_bottom,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
     }
     /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    ///
+    /// Frames of user areas are shared copy-on-write: both spaces map them
+    /// without `W` until one of them stores to the page, see `handle_cow_fault`.
+    /// Other areas (trap context) are copied eagerly, ENOMEM if there are no frames for them.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> SysResult<MemorySet> {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
+            let mut new_area = MapArea::from_another(area);
+            if area.map_type == MapType::Framed && area.map_perm.contains(MapPermission::U) {
+                let mut pte_flags = PTEFlags::from_bits(area.map_perm.bits).unwrap();
+                // pages of a shared file mapping stay writable in both spaces
+                let shared = area.is_shared();
+                if !shared {
+                    pte_flags.remove(PTEFlags::W);
+                }
+                for (vpn, frame) in area.data_frames.iter() {
+                    if !shared {
+                        user_space.page_table.set_flags(*vpn, pte_flags);
+                    }
+                    memory_set.page_table.map(*vpn, frame.ppn, pte_flags);
+                    new_area.data_frames.insert(*vpn, Arc::clone(frame));
+                }
+                // swapped out pages share their slots, each space reads its own copy back
+                for (vpn, slot) in area.swapped.iter() {
+                    new_area.swapped.insert(*vpn, Arc::clone(slot));
+                }
+                memory_set.areas.push(new_area);
+                continue;
+            }
+            memory_set.push(new_area, None)?;
             // copy data from another space
             for vpn in area.vpn_range {
                 let src_ppn = user_space.translate(vpn).unwrap().ppn();
@@ -288,7 +435,161 @@ impl MemorySet {
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
-        memory_set
+        Ok(memory_set)
+    }
+    /// Give the page at `vpn` a private writable frame if it is shared copy-on-write,
+    /// returns false if `vpn` is not a copy-on-write page or no frame can be freed for the copy
+    pub fn handle_cow_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let index = match self.areas.iter().position(|area| area.contains(vpn)) {
+            Some(index) => index,
+            None => return false,
+        };
+        let area = &self.areas[index];
+        if !area.map_perm.contains(MapPermission::W) {
+            return false;
+        }
+        let shared = match area.data_frames.get(&vpn) {
+            Some(frame) => Arc::strong_count(frame) > 1,
+            None => return false,
+        };
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && !pte.writable() => {}
+            _ => return false,
+        }
+        // a shared frame is never chosen for eviction, so the page stays resident
+        if shared && !self.reserve_frames() {
+            return false;
+        }
+        self.areas[index].cow_copy_one(&mut self.page_table, vpn).is_ok()
+    }
+    /// Allocate the frame of a page reserved by a lazy area on its first access,
+    /// or read it back if it was swapped out,
+    /// returns false if `vpn` is not such a page or no frame can be freed for it
+    pub fn handle_lazy_fault(&mut self, vpn: VirtPageNum) -> bool {
+        let index = match self.areas.iter().position(|area| area.contains(vpn)) {
+            Some(index) => index,
+            None => return false,
+        };
+        let area = &self.areas[index];
+        if area.map_type != MapType::Framed
+            || !area.map_perm.contains(MapPermission::U)
+            || area.data_frames.contains_key(&vpn)
+        {
+            return false;
+        }
+        if !self.reserve_frames() || self.areas[index].map_one(&mut self.page_table, vpn).is_err() {
+            return false;
+        }
+        // give the page a second chance before it is evicted again
+        let pte = self.page_table.translate(vpn).unwrap();
+        self.page_table.set_flags(vpn, pte.flags() | PTEFlags::A);
+        true
+    }
+    /// Swap out pages of any process until a page fault can be served,
+    /// returns false if there is nothing left to evict or the swap region is full
+    fn reserve_frames(&mut self) -> bool {
+        while frame_remaining() < FAULT_FRAMES {
+            if !swap_out_any(self) {
+                return false;
+            }
+        }
+        true
+    }
+    /// Evict one resident private user page of this space, chosen by the second-chance clock
+    pub fn swap_out_one(&mut self) -> bool {
+        let mut candidates: Vec<(usize, VirtPageNum)> = Vec::new();
+        for (index, area) in self.areas.iter().enumerate() {
+            if area.map_type != MapType::Framed
+                || !area.map_perm.contains(MapPermission::U)
+                || area.is_shared()
+            {
+                continue;
+            }
+            // frames shared copy-on-write belong to other spaces too
+            for (vpn, frame) in area.data_frames.iter() {
+                if Arc::strong_count(frame) == 1 {
+                    candidates.push((index, *vpn));
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return false;
+        }
+        candidates.sort_by_key(|(_, vpn)| *vpn);
+        let start = candidates
+            .iter()
+            .position(|(_, vpn)| *vpn > self.clock_hand)
+            .unwrap_or(0);
+        // the first round may only clear accessed bits
+        for i in 0..candidates.len() * 2 {
+            let (index, vpn) = candidates[(start + i) % candidates.len()];
+            let flags = self.page_table.translate(vpn).unwrap().flags();
+            if flags.contains(PTEFlags::A) {
+                self.page_table.set_flags(vpn, flags - PTEFlags::A);
+                continue;
+            }
+            self.clock_hand = vpn;
+            return self.areas[index].swap_out_page(&mut self.page_table, vpn);
+        }
+        false
+    }
+    /// Translate the user range [start, start + len) into slices of its frames,
+    /// pages are faulted in first and private copies are made for `write`,
+    /// fails with EFAULT if a page is not accessible from user mode.
+    /// The buffer holds the frames, so they are not evicted while the kernel uses them
+    pub fn translate_user(
+        &mut self,
+        start: usize,
+        len: usize,
+        write: bool,
+    ) -> SysResult<UserBuffer> {
+        let end = start.checked_add(len).ok_or(Errno::EFAULT)?;
+        let start_vpn = VirtAddr::from(start).floor();
+        let end_vpn = VirtAddr::from(end).ceil();
+        // the kernel accesses the frames through physical addresses,
+        // so do the work of the page fault handler beforehand
+        let mut frames = Vec::new();
+        let mut ppns = Vec::new();
+        for vpn in VPNRange::new(start_vpn, end_vpn) {
+            if !self.handle_lazy_fault(vpn) && write {
+                self.handle_cow_fault(vpn);
+            }
+            let pte = match self.page_table.translate(vpn) {
+                Some(pte) if pte.is_valid() => pte,
+                _ => return Err(Errno::EFAULT),
+            };
+            let allowed = if write { pte.writable() } else { pte.readable() };
+            if !pte.flags().contains(PTEFlags::U) || !allowed {
+                return Err(Errno::EFAULT);
+            }
+            // pin the frame right away, faulting in the next pages must not swap it out
+            if let Some(frame) = self
+                .areas
+                .iter()
+                .find(|area| area.contains(vpn))
+                .and_then(|area| area.data_frames.get(&vpn))
+            {
+                frames.push(Arc::clone(frame));
+            }
+            ppns.push(pte.ppn());
+        }
+        let mut v = Vec::new();
+        let mut current = start;
+        for ppn in ppns {
+            let start_va = VirtAddr::from(current);
+            let mut vpn = start_va.floor();
+            vpn.step();
+            let page_end: usize = VirtAddr::from(vpn).into();
+            let end_va = VirtAddr::from(page_end.min(end));
+            let bytes = ppn.get_bytes_array();
+            if end_va.page_offset() == 0 {
+                v.push(&mut bytes[start_va.page_offset()..]);
+            } else {
+                v.push(&mut bytes[start_va.page_offset()..end_va.page_offset()]);
+            }
+            current = end_va.into();
+        }
+        Ok(UserBuffer::new(v, frames))
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -302,16 +603,42 @@ impl MemorySet {
     }
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
+        // write shared file pages back before their frames are freed
+        for area in self.areas.iter_mut().filter(|area| area.is_shared()) {
+            area.unmap(&mut self.page_table);
+        }
         self.areas.clear();
     }
 }
 
+impl Drop for MemorySet {
+    /// exec replaces the memory set without going through `recycle_data_pages`
+    fn drop(&mut self) {
+        self.recycle_data_pages();
+    }
+}
+
+/// the file a framed area is loaded from
+#[derive(Clone)]
+pub struct MapFile {
+    inode: Arc<Inode>,
+    /// offset in the file of the first page of the area
+    offset: usize,
+    /// MAP_SHARED: stores go back to the file, otherwise they stay private
+    shared: bool,
+}
+
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// frames may be shared with other address spaces after fork
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// file backing the pages of a file mapping
+    file: Option<MapFile>,
+    /// swap slots of the pages swapped out, shared with other address spaces after fork
+    swapped: BTreeMap<VirtPageNum, Arc<SwapTracker>>,
 }
 
 impl MapArea {
@@ -328,6 +655,8 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            file: None,
+            swapped: BTreeMap::new(),
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -336,38 +665,158 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            file: another.file.clone(),
+            swapped: BTreeMap::new(),
         }
     }
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+    /// Map the page at `vpn`, ENOMEM if there is no frame for it
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<()> {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc().ok_or(Errno::ENOMEM)?;
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                if let Some(slot) = self.swapped.remove(&vpn) {
+                    slot.read(ppn);
+                } else if let Some(file) = &self.file {
+                    // the frame is cleared, bytes past the end of file stay zero
+                    file.inode.read_at(self.file_offset(vpn), ppn.get_bytes_array());
+                }
+                self.data_frames.insert(vpn, Arc::new(frame));
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        Ok(())
     }
 
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         #[allow(clippy::single_match)]
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                // pages of a lazy area may never have been touched
+                let frame = match self.data_frames.remove(&vpn) {
+                    Some(frame) => frame,
+                    None => {
+                        self.swapped.remove(&vpn);
+                        return;
+                    }
+                };
+                self.write_back(page_table, vpn, &frame);
             }
             _ => {}
         }
         page_table.unmap(vpn);
     }
-    pub fn map(&mut self, page_table: &mut PageTable) {
+    /// Copy a copy-on-write page into a frame of its own and map it writable,
+    /// the last owner of a shared frame keeps it. ENOMEM if there is no frame for the copy
+    pub fn cow_copy_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> SysResult<()> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        let frame = self.data_frames.get(&vpn).unwrap();
+        if Arc::strong_count(frame) == 1 {
+            page_table.set_flags(vpn, pte_flags);
+            return Ok(());
+        }
+        let src_ppn = frame.ppn;
+        let new_frame = frame_alloc().ok_or(Errno::ENOMEM)?;
+        new_frame
+            .ppn
+            .get_bytes_array()
+            .copy_from_slice(src_ppn.get_bytes_array());
+        page_table.unmap(vpn);
+        page_table.map(vpn, new_frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, Arc::new(new_frame));
+        Ok(())
+    }
+    /// Move the end of the area to `new_end`, unmapping the pages above it
+    pub fn resize(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        if new_end < end {
+            for vpn in VPNRange::new(new_end, end) {
+                self.unmap_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = VPNRange::new(start, new_end);
+    }
+    /// Cut the area at `vpn`, self keeps [start, vpn) and the returned area owns [vpn, end)
+    pub fn split_off(&mut self, vpn: VirtPageNum) -> Self {
+        let file = self.file.as_ref().map(|file| MapFile {
+            inode: Arc::clone(&file.inode),
+            offset: self.file_offset(vpn),
+            shared: file.shared,
+        });
+        let tail = Self {
+            vpn_range: VPNRange::new(vpn, self.vpn_range.get_end()),
+            data_frames: self.data_frames.split_off(&vpn),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            file,
+            swapped: self.swapped.split_off(&vpn),
+        };
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), vpn);
+        tail
+    }
+    /// Change the permission of the area and update the ptes of pages already backed by frames,
+    /// frames still shared copy-on-write stay read-only
+    pub fn set_perm(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        let pte_flags = PTEFlags::from_bits(map_perm.bits).unwrap();
+        for (vpn, frame) in self.data_frames.iter() {
+            if Arc::strong_count(frame) > 1 && !self.is_shared() {
+                page_table.set_flags(*vpn, pte_flags - PTEFlags::W);
+            } else {
+                page_table.set_flags(*vpn, pte_flags);
+            }
+        }
+    }
+    /// Write the page at `vpn` to the swap region and free its frame,
+    /// returns false if the swap region is full
+    pub fn swap_out_page(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let slot = match swap_out(self.data_frames.get(&vpn).unwrap().ppn) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        self.data_frames.remove(&vpn);
+        page_table.unmap(vpn);
+        self.swapped.insert(vpn, Arc::new(slot));
+        true
+    }
+    /// Whether the area is a MAP_SHARED file mapping
+    pub fn is_shared(&self) -> bool {
+        matches!(&self.file, Some(file) if file.shared)
+    }
+    /// Offset in the backing file of the page at `vpn`
+    fn file_offset(&self, vpn: VirtPageNum) -> usize {
+        let file = self.file.as_ref().unwrap();
+        file.offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE
+    }
+    /// Write a dirty page of a shared file mapping back to the file,
+    /// the file is never extended by the write
+    fn write_back(&self, page_table: &PageTable, vpn: VirtPageNum, frame: &FrameTracker) {
+        if !self.is_shared() || !page_table.translate(vpn).map_or(false, |pte| pte.dirty()) {
+            return;
+        }
+        let inode = &self.file.as_ref().unwrap().inode;
+        let offset = self.file_offset(vpn);
+        let size = inode.size();
+        if offset < size {
+            let len = PAGE_SIZE.min(size - offset);
+            inode.write_at(offset, &frame.ppn.get_bytes_array()[..len]);
+        }
+    }
+    pub fn contains(&self, vpn: VirtPageNum) -> bool {
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Map every page of the area, ENOMEM if the frames run out on the way
+    pub fn map(&mut self, page_table: &mut PageTable) -> SysResult<()> {
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+            self.map_one(page_table, vpn)?;
         }
+        Ok(())
     }
     pub fn unmap(&mut self, page_table: &mut PageTable) {
         for vpn in self.vpn_range {
@@ -12,14 +12,17 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod swap;
+mod user_ptr;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_dealloc, frame_remaining, FrameTracker};
 pub use memory_set::{remap_test, kernel_token};
 pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_ref, translated_str, PageTableEntry};
+pub use page_table::PageTableEntry;
 pub use page_table::{PTEFlags, PageTable, UserBuffer};
+pub use user_ptr::{UserPtr, UserSlice};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
@@ -0,0 +1,84 @@
+//! Implementation of [`SwapManager`] which
+//! stores evicted user pages in the swap region of the block device.
+
+use super::PhysPageNum;
+use crate::config::{PAGE_SIZE, SWAP_PAGES, SWAP_START_BLOCK};
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+const BLOCK_SZ: usize = 512;
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// allocator of page-sized slots in the swap region
+pub struct SwapManager {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl SwapManager {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            end: SWAP_PAGES,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        if let Some(slot) = self.recycled.pop() {
+            Some(slot)
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some(self.current - 1)
+        }
+    }
+    fn dealloc(&mut self, slot: usize) {
+        // validity check
+        if slot >= self.current || self.recycled.iter().any(|v| *v == slot) {
+            panic!("Swap slot {} has not been allocated!", slot);
+        }
+        self.recycled.push(slot);
+    }
+}
+
+lazy_static! {
+    /// swap manager instance through lazy_static!
+    pub static ref SWAP_MANAGER: UPSafeCell<SwapManager> =
+        unsafe { UPSafeCell::new(SwapManager::new()) };
+}
+
+/// manage a swap slot which has the same lifecycle as the tracker,
+/// spaces copied by fork share the slot through an `Arc`
+pub struct SwapTracker {
+    slot: usize,
+}
+
+impl SwapTracker {
+    /// Read the page stored in the slot into the frame `ppn`
+    pub fn read(&self, ppn: PhysPageNum) {
+        let first_block = SWAP_START_BLOCK + self.slot * BLOCKS_PER_PAGE;
+        for (i, buf) in ppn.get_bytes_array().chunks_mut(BLOCK_SZ).enumerate() {
+            BLOCK_DEVICE.read_block(first_block + i, buf);
+        }
+    }
+}
+
+impl Drop for SwapTracker {
+    fn drop(&mut self) {
+        SWAP_MANAGER.exclusive_access().dealloc(self.slot);
+    }
+}
+
+/// Write the frame `ppn` to a free swap slot, returns None if the swap region is full
+pub fn swap_out(ppn: PhysPageNum) -> Option<SwapTracker> {
+    let slot = SWAP_MANAGER.exclusive_access().alloc()?;
+    let first_block = SWAP_START_BLOCK + slot * BLOCKS_PER_PAGE;
+    for (i, buf) in ppn.get_bytes_array().chunks(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.write_block(first_block + i, buf);
+    }
+    Some(SwapTracker { slot })
+}
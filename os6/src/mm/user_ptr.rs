@@ -0,0 +1,130 @@
+//! Implementation of [`UserPtr`] and [`UserSlice`], the checked way to
+//! access user memory from syscalls.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::{size_of, MaybeUninit};
+
+use crate::config::PAGE_SIZE;
+use crate::errno::{Errno, SysResult};
+
+use super::{MemorySet, UserBuffer};
+
+/// the longest string read from user memory, including paths and arguments
+const USER_STR_LIMIT: usize = 4096;
+
+/// A typed pointer into a user address space, the value may cross a page boundary
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
+
+impl<T> UserPtr<T> {
+    pub fn new(ptr: *const T) -> Self {
+        Self {
+            addr: ptr as usize,
+            _marker: PhantomData,
+        }
+    }
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+    /// The pointer `count` elements after this one
+    pub fn add(self, count: usize) -> Self {
+        Self {
+            addr: self.addr.wrapping_add(count * size_of::<T>()),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    /// Copy the value out of user memory
+    pub fn read(&self, memory_set: &mut MemorySet) -> SysResult<T> {
+        if self.is_null() {
+            return Err(Errno::EFAULT);
+        }
+        let mut value = MaybeUninit::<T>::uninit();
+        let dst = value.as_mut_ptr() as *mut u8;
+        let buffer = memory_set.translate_user(self.addr, size_of::<T>(), false)?;
+        for (i, byte_ref) in buffer.into_iter().enumerate() {
+            unsafe {
+                *dst.add(i) = *byte_ref;
+            }
+        }
+        Ok(unsafe { value.assume_init() })
+    }
+    /// Copy `value` into user memory
+    pub fn write(&self, memory_set: &mut MemorySet, value: T) -> SysResult<()> {
+        if self.is_null() {
+            return Err(Errno::EFAULT);
+        }
+        let src = &value as *const T as *const u8;
+        let buffer = memory_set.translate_user(self.addr, size_of::<T>(), true)?;
+        for (i, byte_ref) in buffer.into_iter().enumerate() {
+            unsafe {
+                *byte_ref = *src.add(i);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UserPtr<u8> {
+    /// Read a '\0' terminated string, fails with EFAULT if it runs into an inaccessible page
+    /// and EINVAL if it is longer than `USER_STR_LIMIT`
+    pub fn read_str(&self, memory_set: &mut MemorySet) -> SysResult<String> {
+        if self.is_null() {
+            return Err(Errno::EFAULT);
+        }
+        let mut bytes: Vec<u8> = Vec::new();
+        let mut addr = self.addr;
+        loop {
+            // never translate past the end of the current page
+            let page_left = PAGE_SIZE - addr % PAGE_SIZE;
+            for slice in memory_set.translate_user(addr, page_left, false)?.buffers {
+                if let Some(len) = slice.iter().position(|byte| *byte == 0) {
+                    bytes.extend_from_slice(&slice[..len]);
+                    return String::from_utf8(bytes).map_err(|_| Errno::EINVAL);
+                }
+                bytes.extend_from_slice(slice);
+            }
+            if bytes.len() >= USER_STR_LIMIT {
+                return Err(Errno::EINVAL);
+            }
+            addr += page_left;
+        }
+    }
+}
+
+/// A range of bytes in a user address space
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(ptr: *const u8, len: usize) -> Self {
+        Self {
+            addr: ptr as usize,
+            len,
+        }
+    }
+    /// Get the frames of the range for the kernel to read from
+    pub fn reader(&self, memory_set: &mut MemorySet) -> SysResult<UserBuffer> {
+        memory_set.translate_user(self.addr, self.len, false)
+    }
+    /// Get the frames of the range for the kernel to write to
+    pub fn writer(&self, memory_set: &mut MemorySet) -> SysResult<UserBuffer> {
+        memory_set.translate_user(self.addr, self.len, true)
+    }
+}
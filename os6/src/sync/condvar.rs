@@ -0,0 +1,40 @@
+//! Condition variables for the threads of a process
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, WaitQueue};
+
+/// A condition variable, used together with a [`Mutex`]
+pub struct Condvar {
+    inner: UPSafeCell<CondvarInner>,
+}
+
+pub struct CondvarInner {
+    wait_queue: WaitQueue,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(CondvarInner {
+                    wait_queue: WaitQueue::new(),
+                })
+            },
+        }
+    }
+
+    /// Wake up one waiting thread
+    pub fn signal(&self) {
+        self.inner.exclusive_access().wait_queue.wake_one();
+    }
+
+    /// Block until signaled, the caller unlocks the mutex before and locks it again after
+    pub fn wait(&self) {
+        self.inner.exclusive_access().wait_queue.add_current();
+        block_current_and_run_next();
+        // the process is exiting, the caller does not wait for the mutex again
+        if current_killed() {
+            self.inner.exclusive_access().wait_queue.remove_current();
+        }
+    }
+}
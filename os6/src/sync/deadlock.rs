@@ -0,0 +1,117 @@
+//! Deadlock detection with the banker's algorithm
+//!
+//! The threads of a process request, acquire and release the resources of one kind
+//! of sync object, a request which leaves no order for all the threads to finish
+//! would deadlock.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Resource matrices of one kind of sync object, indexed by tid and object id
+#[derive(Default)]
+pub struct DeadlockDetector {
+    /// Free resources of every object
+    available: Vec<usize>,
+    /// Resources held by every thread
+    allocation: Vec<Vec<usize>>,
+    /// Resources every thread is waiting for
+    need: Vec<Vec<usize>>,
+}
+
+impl DeadlockDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking object `id` with `count` resources, the id may be a reused one
+    pub fn add_resource(&mut self, id: usize, count: usize) {
+        if self.available.len() <= id {
+            self.available.resize(id + 1, 0);
+        }
+        self.available[id] = count;
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            if let Some(slot) = row.get_mut(id) {
+                *slot = 0;
+            }
+        }
+    }
+
+    /// Make sure the rows of `tid` and the column of `id` exist
+    fn reserve(&mut self, tid: usize, id: usize) {
+        let width = self.available.len().max(id + 1);
+        self.available.resize(width, 0);
+        if self.allocation.len() <= tid {
+            self.allocation.resize(tid + 1, Vec::new());
+            self.need.resize(tid + 1, Vec::new());
+        }
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            row.resize(width, 0);
+        }
+    }
+
+    /// Record that thread `tid` waits for a resource of object `id`.
+    /// Returns false and forgets the request if it leads to a deadlock
+    pub fn request(&mut self, tid: usize, id: usize) -> bool {
+        self.reserve(tid, id);
+        self.need[tid][id] += 1;
+        if self.is_safe() {
+            true
+        } else {
+            self.need[tid][id] -= 1;
+            false
+        }
+    }
+
+    /// Thread `tid` got a resource of object `id`
+    pub fn acquire(&mut self, tid: usize, id: usize) {
+        self.reserve(tid, id);
+        self.need[tid][id] = self.need[tid][id].saturating_sub(1);
+        debug_assert!(self.available[id] > 0, "resource {} acquired but not available", id);
+        self.available[id] -= 1;
+        self.allocation[tid][id] += 1;
+    }
+
+    /// Thread `tid` gave back a resource of object `id`,
+    /// a semaphore may be signaled by a thread which does not hold it
+    pub fn release(&mut self, tid: usize, id: usize) {
+        self.reserve(tid, id);
+        self.allocation[tid][id] = self.allocation[tid][id].saturating_sub(1);
+        self.available[id] += 1;
+    }
+
+    /// Thread `tid` exited, it neither waits any more nor gives back what it holds,
+    /// the rows are clean for a thread reusing the tid
+    pub fn remove_thread(&mut self, tid: usize) {
+        if let Some(row) = self.allocation.get_mut(tid) {
+            row.iter_mut().for_each(|slot| *slot = 0);
+        }
+        if let Some(row) = self.need.get_mut(tid) {
+            row.iter_mut().for_each(|slot| *slot = 0);
+        }
+    }
+
+    /// Whether the threads can finish one after another,
+    /// each getting what it needs from the resources released by the former ones
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut finish = vec![false; self.need.len()];
+        loop {
+            let next = (0..self.need.len()).find(|&tid| {
+                !finish[tid]
+                    && self.need[tid]
+                        .iter()
+                        .zip(work.iter())
+                        .all(|(need, work)| need <= work)
+            });
+            match next {
+                Some(tid) => {
+                    for (work, allocation) in work.iter_mut().zip(self.allocation[tid].iter()) {
+                        *work += allocation;
+                    }
+                    finish[tid] = true;
+                }
+                None => return finish.into_iter().all(|finished| finished),
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Mutexes for the threads of a process
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, suspend_current_and_run_next, WaitQueue};
+
+/// Mutual exclusion between the threads of a process
+pub trait Mutex: Sync + Send {
+    fn lock(&self);
+    /// Returns false if the mutex is not locked
+    fn unlock(&self) -> bool;
+}
+
+/// A mutex which yields the CPU until it is released
+pub struct MutexSpin {
+    locked: UPSafeCell<bool>,
+}
+
+impl MutexSpin {
+    pub fn new() -> Self {
+        Self {
+            locked: unsafe { UPSafeCell::new(false) },
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) {
+        loop {
+            let mut locked = self.locked.exclusive_access();
+            if *locked {
+                drop(locked);
+                suspend_current_and_run_next();
+                continue;
+            } else {
+                *locked = true;
+                return;
+            }
+        }
+    }
+
+    fn unlock(&self) -> bool {
+        let mut locked = self.locked.exclusive_access();
+        let was_locked = *locked;
+        *locked = false;
+        was_locked
+    }
+}
+
+/// A mutex which blocks the waiting threads
+pub struct MutexBlocking {
+    inner: UPSafeCell<MutexBlockingInner>,
+}
+
+pub struct MutexBlockingInner {
+    locked: bool,
+    wait_queue: WaitQueue,
+}
+
+impl MutexBlocking {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(MutexBlockingInner {
+                    locked: false,
+                    wait_queue: WaitQueue::new(),
+                })
+            },
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.locked {
+            mutex_inner.wait_queue.add_current();
+            drop(mutex_inner);
+            // the mutex is still locked and handed over to us by unlock
+            block_current_and_run_next();
+            // unless the process is exiting, then it is not ours
+            if current_killed() {
+                self.inner.exclusive_access().wait_queue.remove_current();
+            }
+        } else {
+            mutex_inner.locked = true;
+        }
+    }
+
+    fn unlock(&self) -> bool {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !mutex_inner.locked {
+            return false;
+        }
+        if !mutex_inner.wait_queue.wake_one() {
+            mutex_inner.locked = false;
+        }
+        true
+    }
+}
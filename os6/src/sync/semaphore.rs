@@ -0,0 +1,52 @@
+//! Counting semaphores for the threads of a process
+
+use super::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_killed, WaitQueue};
+
+/// A semaphore which blocks the threads waiting for a resource
+pub struct Semaphore {
+    inner: UPSafeCell<SemaphoreInner>,
+}
+
+pub struct SemaphoreInner {
+    /// Number of available resources
+    count: usize,
+    wait_queue: WaitQueue,
+}
+
+impl Semaphore {
+    pub fn new(res_count: usize) -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(SemaphoreInner {
+                    count: res_count,
+                    wait_queue: WaitQueue::new(),
+                })
+            },
+        }
+    }
+
+    /// Release a resource, it goes to the first waiting thread if there is one
+    pub fn up(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if !inner.wait_queue.wake_one() {
+            inner.count += 1;
+        }
+    }
+
+    /// Acquire a resource, block until one is released if there is none
+    pub fn down(&self) {
+        let mut inner = self.inner.exclusive_access();
+        if inner.count > 0 {
+            inner.count -= 1;
+        } else {
+            inner.wait_queue.add_current();
+            drop(inner);
+            block_current_and_run_next();
+            // the process is exiting, it does not get the resource
+            if current_killed() {
+                self.inner.exclusive_access().wait_queue.remove_current();
+            }
+        }
+    }
+}
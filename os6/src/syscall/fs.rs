@@ -1,116 +1,205 @@
 //! File and filesystem-related syscalls
 
 use alloc::sync::Arc;
-use core::mem::size_of;
+use alloc::string::String;
 
-use crate::fs::{File, linkat, open_file, OSInode, StatMode, Stdin, unlinkat};
+use crate::errno::{Errno, SysResult};
+use crate::fs::{dir_path, File, FileDescriptor, find_inode, find_link, linkat, mkdirat, open_file, OSInode, readlinkat, renameat, StatMode, Stdin, symlinkat, unlinkat};
 use crate::fs::make_pipe;
 use crate::fs::OpenFlags;
 use crate::fs::Stat;
-use crate::mm::translated_byte_buffer;
-use crate::mm::translated_refmut;
-use crate::mm::translated_str;
-use crate::mm::UserBuffer;
-use crate::task::current_task;
-use crate::task::current_user_token;
-
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.write(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
-    } else {
-        -1
+use crate::mm::{UserPtr, UserSlice};
+use crate::task::current_process;
+use crate::timer::get_time_sec;
+
+/// Get the file opened at `fd` by the current process
+fn get_file(fd: usize) -> SysResult<Arc<dyn File + Send + Sync>> {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    match inner.fd_table.get(fd) {
+        Some(Some(fd)) => Ok(fd.file.clone()),
+        _ => Err(Errno::EBADF),
     }
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
     }
-    if let Some(file) = &inner.fd_table[fd] {
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(inner);
-        file.read(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
-    } else {
-        -1
-    }
-}
-
-pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    // println!("sys_open: {}", path);
-    let ret = if let Some(inode) = open_file(
-        path.as_str(),
-        OpenFlags::from_bits(flags).unwrap(),
-    ) {
-        let mut inner = task.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
-    } else {
-        -1
-    };
-    // println!("sys_open {} ret={}", path, ret);
-    ret
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).reader(&mut process.inner_exclusive_access().memory_set)?;
+    // the write may block, do not keep the process alive meanwhile
+    drop(process);
+    file.write(user_buf)
+}
+
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
+    }
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).writer(&mut process.inner_exclusive_access().memory_set)?;
+    // the read may block, do not keep the process alive meanwhile
+    drop(process);
+    Ok(file.read(user_buf))
 }
 
-pub fn sys_close(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+/// Move the offset of the file at `fd`, returns the new offset
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    get_file(fd)?.seek(offset, whence)
+}
+
+/// Read the entries of the directory opened at `fd` into `buf` as `linux_dirent64` records
+pub fn sys_getdents64(fd: usize, buf: *const u8, len: usize) -> SysResult {
+    let file = get_file(fd)?;
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).writer(&mut process.inner_exclusive_access().memory_set)?;
+    file.getdents(user_buf)
+}
+
+/// Read at `offset` of the file at `fd`, its offset is not changed
+pub fn sys_pread64(fd: usize, buf: *const u8, len: usize, offset: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.readable() {
+        return Err(Errno::EBADF);
     }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).writer(&mut process.inner_exclusive_access().memory_set)?;
+    file.read_at(offset, user_buf)
+}
+
+/// Write at `offset` of the file at `fd`, its offset is not changed
+pub fn sys_pwrite64(fd: usize, buf: *const u8, len: usize, offset: usize) -> SysResult {
+    let file = get_file(fd)?;
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).reader(&mut process.inner_exclusive_access().memory_set)?;
+    file.write_at(offset, user_buf)
+}
+
+/// Read the path at `path` from the memory of the current process
+fn read_path(path: *const u8) -> SysResult<String> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    UserPtr::new(path).read_str(&mut inner.memory_set)
+}
+
+pub fn sys_open(path: *const u8, flags: u32) -> SysResult {
+    let process = current_process();
+    let path = read_path(path)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let inode = open_file(&cwd, path.as_str(), flags)?;
+    let mut inner = process.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(FileDescriptor::new(inode, flags.contains(OpenFlags::CLOEXEC)));
+    Ok(fd)
+}
+
+pub fn sys_close(fd: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.fd_table.get_mut(fd).and_then(|file| file.take()) {
+        Some(_) => Ok(0),
+        None => Err(Errno::EBADF),
     }
-    inner.fd_table[fd].take();
-    0
 }
 
-pub fn sys_pipe(pipe: *mut usize) -> isize {
-    let task = current_task().unwrap();
-    let token = current_user_token();
-    let mut inner = task.inner_exclusive_access();
+pub fn sys_pipe(pipe: *mut usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let pipe = UserPtr::<[usize; 2]>::new(pipe as *const [usize; 2]);
+    // fail before any fd is allocated
+    pipe.write(&mut inner.memory_set, [0; 2])?;
     let (pipe_read, pipe_write) = make_pipe();
     let read_fd = inner.alloc_fd();
-    inner.fd_table[read_fd] = Some(pipe_read);
+    inner.fd_table[read_fd] = Some(FileDescriptor::new(pipe_read, false));
     let write_fd = inner.alloc_fd();
-    inner.fd_table[write_fd] = Some(pipe_write);
-    *translated_refmut(token, pipe) = read_fd;
-    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
-    0
+    inner.fd_table[write_fd] = Some(FileDescriptor::new(pipe_write, false));
+    pipe.write(&mut inner.memory_set, [read_fd, write_fd])?;
+    Ok(0)
 }
 
-pub fn sys_dup(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
-    }
-    if inner.fd_table[fd].is_none() {
-        return -1;
+/// Get the fd flags
+const F_GETFD: usize = 1;
+/// Set the fd flags
+const F_SETFD: usize = 2;
+/// The only fd flag, close the fd on exec
+const FD_CLOEXEC: usize = 1;
+/// Get the capacity of a pipe
+const F_GETPIPE_SZ: usize = 1032;
+/// Set the capacity of a pipe
+const F_SETPIPE_SZ: usize = 1031;
+
+/// Manipulate the fd `fd`, only the fd flags and pipe capacity commands are supported
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.fd_table.get_mut(fd) {
+        Some(Some(fd)) => fd,
+        _ => return Err(Errno::EBADF),
+    };
+    match cmd {
+        F_GETFD => Ok(if fd.cloexec { FD_CLOEXEC } else { 0 }),
+        F_SETFD => {
+            fd.cloexec = arg & FD_CLOEXEC != 0;
+            Ok(0)
+        }
+        F_GETPIPE_SZ | F_SETPIPE_SZ => {
+            let file = fd.file.clone();
+            // resizing a pipe wakes up its writers
+            drop(inner);
+            if cmd == F_GETPIPE_SZ {
+                file.pipe_size()
+            } else {
+                file.set_pipe_size(arg)
+            }
+        }
+        _ => Err(Errno::EINVAL),
     }
+}
+
+/// The new fd is not close-on-exec
+pub fn sys_dup(fd: usize) -> SysResult {
+    let file = get_file(fd)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     let new_fd = inner.alloc_fd();
-    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
-    new_fd as isize
+    inner.fd_table[new_fd] = Some(FileDescriptor::new(file, false));
+    Ok(new_fd)
+}
+
+/// `new_fd` of dup3 has to be below it
+const FD_LIMIT: usize = 1024;
+
+/// Make `new_fd` refer to the file of `old_fd`, closing the file `new_fd` referred to.
+/// `flags` may only contain [`OpenFlags::CLOEXEC`]
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> SysResult {
+    let file = get_file(old_fd)?;
+    if new_fd >= FD_LIMIT {
+        return Err(Errno::EBADF);
+    }
+    let flags = OpenFlags::from_bits(flags).ok_or(Errno::EINVAL)?;
+    if old_fd == new_fd || !(flags - OpenFlags::CLOEXEC).is_empty() {
+        return Err(Errno::EINVAL);
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    // the replaced file is dropped after the process is released, a pipe end wakes up waiters
+    let old_file = inner.fd_table[new_fd].replace(FileDescriptor::new(
+        file,
+        flags.contains(OpenFlags::CLOEXEC),
+    ));
+    drop(inner);
+    drop(old_file);
+    Ok(new_fd)
 }
 
 /**
@@ -118,44 +207,183 @@ pub fn sys_dup(fd: usize) -> isize {
 问题是unlink的时候,要检测到inode还在另一个文件夹中被引用, 所以diskinode肯定要知道这种情况.
  */
 
-pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
-    let buffers = translated_byte_buffer(current_user_token(), st as *mut u8, size_of::<Stat>());
-    assert_eq!(1, buffers.len());
-    let st = unsafe { (buffers[0].as_ptr() as *mut Stat).as_mut().unwrap() };
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> SysResult {
     //剩下来的就是普通文件,文件夹了.
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() {
-        return -1;
+    let stat = get_file(fd)?.stat();
+    let process = current_process();
+    UserPtr::new(st).write(&mut process.inner_exclusive_access().memory_set, stat)?;
+    Ok(0)
+}
+
+/// Stat the file at `name`, a symbolic link at the end of the path is reported
+/// itself instead of its target with [`AT_SYMLINK_NOFOLLOW`]
+pub fn sys_fstatat(name: *const u8, st: *mut Stat, flags: u32) -> SysResult {
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return Err(Errno::EINVAL);
     }
-    *st = inner.fd_table[fd].as_ref().unwrap().stat();
-    0
+    let path = read_path(name)?;
+    let process = current_process();
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let inode = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        find_link(&cwd, &path)?
+    } else {
+        find_inode(&cwd, &path)?
+    };
+    let stat = Stat::new(&inode.stat());
+    UserPtr::new(st).write(&mut process.inner_exclusive_access().memory_set, stat)?;
+    Ok(0)
 }
 
-pub fn sys_linkat(old_name: *const u8, new_name: *const u8) -> isize {
-    let token = current_user_token();
-    let path_old = translated_str(token, old_name);
-    let path_new = translated_str(token, new_name);
+pub fn sys_linkat(old_name: *const u8, new_name: *const u8) -> SysResult {
+    let path_old = read_path(old_name)?;
+    let path_new = read_path(new_name)?;
     if path_new == path_old {
-        return -1;
+        return Err(Errno::EEXIST);
+    }
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    linkat(&cwd, &path_old, &path_new)?;
+    Ok(0)
+}
+
+pub fn sys_unlinkat(name: *const u8) -> SysResult {
+    let path = read_path(name)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    unlinkat(&cwd, &path)?;
+    Ok(0)
+}
+
+/// Create a symbolic link `link_name` pointing to `target`
+pub fn sys_symlinkat(target: *const u8, link_name: *const u8) -> SysResult {
+    let target = read_path(target)?;
+    let path = read_path(link_name)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    symlinkat(&cwd, &target, &path)?;
+    Ok(0)
+}
+
+/// Copy the target of a symbolic link into `buf` without a trailing '\0',
+/// it is truncated to `len` bytes. Returns the number of bytes copied
+pub fn sys_readlinkat(name: *const u8, buf: *mut u8, len: usize) -> SysResult {
+    let path = read_path(name)?;
+    let process = current_process();
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let target = readlinkat(&cwd, &path)?;
+    let len = target.len().min(len);
+    let user_buf = UserSlice::new(buf, len)
+        .writer(&mut process.inner_exclusive_access().memory_set)?;
+    for (byte_ref, byte) in user_buf.into_iter().zip(target.bytes()) {
+        unsafe { *byte_ref = byte; }
+    }
+    Ok(len)
+}
+
+/// `flags` of [`sys_renameat2`]: fail with EEXIST instead of replacing the target
+const RENAME_NOREPLACE: u32 = 1 << 0;
+
+/// Rename a file or directory, relative paths start from the cwd like [`sys_linkat`].
+/// Only [`RENAME_NOREPLACE`] is supported in `flags`
+pub fn sys_renameat2(old_name: *const u8, new_name: *const u8, flags: u32) -> SysResult {
+    if flags & !RENAME_NOREPLACE != 0 {
+        return Err(Errno::EINVAL);
     }
-    // println!("linkat {} {} start", path_old, path_new);
-    if let Some(_) = linkat(&path_old, &path_new) {
-        // println!("linkat {} {} success", path_old, path_new);
-        return 0;
+    let path_old = read_path(old_name)?;
+    let path_new = read_path(new_name)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    renameat(&cwd, &path_old, &path_new, flags & RENAME_NOREPLACE != 0)?;
+    Ok(0)
+}
+
+pub fn sys_mkdirat(path: *const u8) -> SysResult {
+    let path = read_path(path)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    mkdirat(&cwd, &path)?;
+    Ok(0)
+}
+
+pub fn sys_chdir(path: *const u8) -> SysResult {
+    let path = read_path(path)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let inode = find_inode(&inner.cwd, &path)?;
+    if !inode.is_dir() {
+        return Err(Errno::ENOTDIR);
     }
-    println!("linkat {} {} failed", path_old, path_new);
-    -1
+    inner.cwd = inode;
+    Ok(0)
+}
+
+/// Change the permission bits of a file, symbolic links are followed
+pub fn sys_fchmodat(name: *const u8, mode: u32) -> SysResult {
+    let path = read_path(name)?;
+    let cwd = current_process().inner_exclusive_access().cwd.clone();
+    find_inode(&cwd, &path)?.set_mode((mode & 0o777) as u16);
+    Ok(0)
+}
+
+/// `struct timespec` of [`sys_utimensat`]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
 }
 
-pub fn sys_unlinkat(name: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, name);
+/// `nsec` of [`TimeSpec`]: set to the current time
+const UTIME_NOW: usize = (1 << 30) - 1;
+/// `nsec` of [`TimeSpec`]: keep the old time
+const UTIME_OMIT: usize = (1 << 30) - 2;
+/// Do not follow a symbolic link at the end of the path
+const AT_SYMLINK_NOFOLLOW: u32 = 0x100;
+
+/// Set the access and modification times of a file to `times[0]` and `times[1]`,
+/// both are set to the current time if `times` is null
+pub fn sys_utimensat(name: *const u8, times: *const TimeSpec, flags: u32) -> SysResult {
+    if flags & !AT_SYMLINK_NOFOLLOW != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let path = read_path(name)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let now = TimeSpec { sec: 0, nsec: UTIME_NOW };
+    let (atime, mtime) = if times.is_null() {
+        (now, now)
+    } else {
+        let times = UserPtr::new(times);
+        (times.read(&mut inner.memory_set)?, times.add(1).read(&mut inner.memory_set)?)
+    };
+    let cwd = inner.cwd.clone();
+    drop(inner);
+    let inode = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+        find_link(&cwd, &path)?
+    } else {
+        find_inode(&cwd, &path)?
+    };
+    // the times are in whole seconds on disk
+    let to_sec = |time: TimeSpec| match time.nsec {
+        UTIME_NOW => Ok(Some(get_time_sec())),
+        UTIME_OMIT => Ok(None),
+        nsec if nsec < 1_000_000_000 => Ok(Some(time.sec as u32)),
+        _ => Err(Errno::EINVAL),
+    };
+    inode.set_times(to_sec(atime)?, to_sec(mtime)?);
+    Ok(0)
+}
 
-    if let Some(_) = unlinkat(&path) {
-        // println!("unlinkat {} success", path);
-        return 0;
+/// Copy the absolute path of the working directory with a trailing '\0' into `buf`,
+/// returns the number of bytes copied
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> SysResult {
+    let process = current_process();
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    // the working directory has been removed
+    let mut path = dir_path(&cwd).ok_or(Errno::ENOENT)?;
+    path.push('\0');
+    if path.len() > len {
+        return Err(Errno::ERANGE);
+    }
+    let user_buf = UserSlice::new(buf, path.len())
+        .writer(&mut process.inner_exclusive_access().memory_set)?;
+    for (byte_ref, byte) in user_buf.into_iter().zip(path.bytes()) {
+        unsafe { *byte_ref = byte; }
     }
-    println!("unlinkat {} failed", path);
-    -1
+    Ok(path.len())
 }
@@ -0,0 +1,52 @@
+//! Mailbox syscalls between processes
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::UserSlice;
+use crate::task::{current_process, pid2process, MAIL_SIZE};
+use alloc::vec::Vec;
+
+/// Take the oldest message of the current process into `buf`, truncated to `len` bytes,
+/// returns the number of bytes copied.
+/// EAGAIN if the mailbox is empty, with `len` 0 only checks that there is a message
+pub fn sys_mail_read(buf: *mut u8, len: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner.mailbox.is_empty() {
+        return Err(Errno::EAGAIN);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let len = len.min(MAIL_SIZE);
+    let user_buf = UserSlice::new(buf, len).writer(&mut inner.memory_set)?;
+    let mail = inner.mailbox.pop().unwrap();
+    let mut copied = 0;
+    for (byte_ref, byte) in user_buf.into_iter().zip(mail.iter()) {
+        unsafe {
+            *byte_ref = *byte;
+        }
+        copied += 1;
+    }
+    Ok(copied)
+}
+
+/// Put the `len` bytes at `buf`, truncated to [`MAIL_SIZE`], into the mailbox of process `pid`,
+/// returns the number of bytes sent.
+/// ESRCH if there is no such process, ENOSPC if its mailbox is full,
+/// with `len` 0 only checks that there is room for a message
+pub fn sys_mail_write(pid: usize, buf: *const u8, len: usize) -> SysResult {
+    let target = pid2process(pid).ok_or(Errno::ESRCH)?;
+    if target.inner_exclusive_access().mailbox.is_full() {
+        return Err(Errno::ENOSPC);
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let len = len.min(MAIL_SIZE);
+    // the target may be the current process, do not borrow both at once
+    let process = current_process();
+    let user_buf = UserSlice::new(buf, len).reader(&mut process.inner_exclusive_access().memory_set)?;
+    let mail: Vec<u8> = user_buf.into_iter().map(|byte_ref| unsafe { *byte_ref }).collect();
+    target.inner_exclusive_access().mailbox.push(mail);
+    Ok(len)
+}
@@ -1,17 +1,24 @@
 //! Process management syscalls
 
-use crate::mm::{MapPermission, translated_byte_buffer, translated_refmut, translated_str, VirtAddr};
-use crate::task::{add_task, current_task, current_user_token, exit_current_and_run_next, suspend_current_and_run_next, take_current_task, TaskControlBlock, TaskStatus};
-use crate::timer::{get_time_milli, get_time_us};
+use crate::errno::{Errno, SysResult};
+use crate::mm::{MapPermission, MemorySet, UserPtr, VirtAddr, VirtPageNum};
+use crate::task::{
+    block_current_and_run_next, current_killed, current_process, current_task, current_tid,
+    exit_current_and_run_next, pid2process, suspend_current_and_run_next, wake_for_fatal_signal,
+    ProcessControlBlock, SignalAction, SignalFlags, TaskStatus,
+};
+use crate::timer::{add_timer, get_time_milli, get_time_us, remove_timer};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::mem::size_of;
 
-use crate::config::MAX_SYSCALL_NUM;
+use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE, USER_STACK_SIZE};
 use crate::fs::{open_file, OpenFlags};
 use crate::sbi::shutdown;
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
@@ -26,7 +33,7 @@ pub struct TaskInfo {
 
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
-    if current_task().unwrap().pid.0==0 {
+    if current_process().getpid() == 0 && current_tid() == 0 {
         println!("pid 0 cannot quit");
         shutdown();
     }
@@ -35,105 +42,172 @@ pub fn sys_exit(exit_code: i32) -> ! {
 }
 
 /// current task gives up resources for other tasks
-pub fn sys_yield() -> isize {
+pub fn sys_yield() -> SysResult {
     suspend_current_and_run_next();
-    0
+    Ok(0)
 }
 
-pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+/// Block the current thread for at least `ms` milliseconds, like nanosleep.
+/// The deadline is checked on every timer tick
+pub fn sys_sleep(ms: usize) -> SysResult {
+    if ms == 0 {
+        suspend_current_and_run_next();
+        return Ok(0);
+    }
+    let expire_ms = get_time_milli() + ms;
+    let task = current_task().unwrap();
+    add_timer(expire_ms, task.clone());
+    block_current_and_run_next();
+    if current_killed() {
+        remove_timer(&task);
+    }
+    Ok(0)
+}
+
+pub fn sys_getpid() -> SysResult {
+    Ok(current_process().getpid())
+}
+
+/// Fork and exec are only supported on the main thread
+/// of a process whose other threads have exited
+fn check_single_threaded(process: &ProcessControlBlock) -> SysResult<()> {
+    if current_tid() != 0 || !process.inner_exclusive_access().is_single_threaded() {
+        return Err(Errno::EINVAL);
+    }
+    Ok(())
 }
 
 /// Syscall Fork which returns 0 for child process and child_pid for parent process
-pub fn sys_fork() -> isize {
-    let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
-    // modify trap context of new_task, because it returns immediately after switching
-    let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
+pub fn sys_fork() -> SysResult {
+    let current_process = current_process();
+    check_single_threaded(&current_process)?;
+    let new_process = current_process.fork()?;
+    let new_pid = new_process.getpid();
+    // modify trap context of the main thread, because it returns immediately after switching
+    let task = new_process.inner_exclusive_access().get_task(0);
+    let trap_cx = task.inner_exclusive_access().get_trap_cx();
     // we do not have to move to next instruction since we have done it before
     // for child process, fork returns 0
     trap_cx.x[10] = 0;
-    // add new task to scheduler
-    add_task(new_task);
-    new_pid as isize
-}
-
-/// Syscall Exec which accepts the elf path
-pub fn sys_exec(path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(),OpenFlags::RDONLY) {
-        let data=inode.read_all();
-        let task = current_task().unwrap();
-        task.exec(data.as_slice());
-        0
-    } else {
-        -1
+    Ok(new_pid)
+}
+
+/// At most half of the user stack is taken by the arguments
+const ARGS_SIZE_LIMIT: usize = USER_STACK_SIZE / 2;
+
+/// Collect the null-terminated array of argument strings at `args`,
+/// a null `args` is treated as an empty array.
+/// Return E2BIG if the arguments do not fit in [`ARGS_SIZE_LIMIT`]
+fn translated_args(memory_set: &mut MemorySet, args: *const usize) -> SysResult<Vec<String>> {
+    let mut args_vec: Vec<String> = Vec::new();
+    let args = UserPtr::new(args);
+    if args.is_null() {
+        return Ok(args_vec);
     }
+    // the null pointer ending argv
+    let mut total = size_of::<usize>();
+    loop {
+        let arg_str_ptr = args.add(args_vec.len()).read(memory_set)?;
+        if arg_str_ptr == 0 {
+            break;
+        }
+        let arg = UserPtr::new(arg_str_ptr as *const u8).read_str(memory_set)?;
+        total += size_of::<usize>() + arg.len() + 1;
+        if total > ARGS_SIZE_LIMIT {
+            return Err(Errno::E2BIG);
+        }
+        args_vec.push(arg);
+    }
+    Ok(args_vec)
 }
 
-/// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let task = current_task().unwrap();
+/// Read the path and the arguments of exec and spawn
+fn translated_path_args(path: *const u8, args: *const usize) -> SysResult<(String, Vec<String>)> {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let path = UserPtr::new(path).read_str(&mut inner.memory_set)?;
+    let args_vec = translated_args(&mut inner.memory_set, args)?;
+    Ok((path, args_vec))
+}
+
+/// Syscall Exec which accepts the elf path and a null-terminated argv,
+/// returns argc which becomes a0 of the new program
+pub fn sys_exec(path: *const u8, args: *const usize) -> SysResult {
+    let (path, args_vec) = translated_path_args(path, args)?;
+    let process = current_process();
+    check_single_threaded(&process)?;
+    let cwd = process.inner_exclusive_access().cwd.clone();
+    let inode = open_file(&cwd, path.as_str(), OpenFlags::RDONLY)?;
+    let data=inode.read_all();
+    let argc = args_vec.len();
+    process.exec(data.as_slice(), args_vec)?;
+    Ok(argc)
+}
+
+/// If there is not a child process whose pid is same as given, return ECHILD.
+/// Else if there is a child process but it is still running, return EAGAIN.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> SysResult {
+    let process = current_process();
     // find a child process
 
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
     if !inner
         .children
         .iter()
         .any(|p| pid == -1 || pid as usize == p.getpid())
     {
-        return -1;
+        return Err(Errno::ECHILD);
         // ---- release current PCB
     }
     let pair = inner.children.iter().enumerate().find(|(_, p)| {
         // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
         // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
+    }).map(|(idx, p)| (idx, p.clone()));
+    if let Some((idx, child)) = pair {
+        // ++++ temporarily access child PCB exclusively
         let exit_code = child.inner_exclusive_access().exit_code;
         // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
+        let exit_code_ptr = UserPtr::new(exit_code_ptr);
+        if !exit_code_ptr.is_null() {
+            // the child is still there to be waited if the address is bad
+            exit_code_ptr.write(&mut inner.memory_set, exit_code)?;
+        }
+        inner.children.remove(idx);
+        // confirm that child will be deallocated after removing from children list
+        assert_eq!(Arc::strong_count(&child), 1);
+        Ok(child.getpid())
     } else {
-        -2
+        Err(Errno::EAGAIN)
     }
     // ---- release current PCB lock automatically
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_get_time
-pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> isize {
-    let buffers = translated_byte_buffer(current_user_token(), _ts as *mut u8, size_of::<TimeVal>());
-    assert_eq!(1, buffers.len());
-    let ts = unsafe { (buffers[0].as_ptr() as *mut TimeVal).as_mut().unwrap() };
+pub fn sys_get_time(_ts: *mut TimeVal, _tz: usize) -> SysResult {
     let us = get_time_us();
-    ts.sec = us / 1_000_000;
-    ts.usec = us % 1_000_000;
-    0
+    let ts = TimeVal {
+        sec: us / 1_000_000,
+        usec: us % 1_000_000,
+    };
+    let process = current_process();
+    UserPtr::new(_ts).write(&mut process.inner_exclusive_access().memory_set, ts)?;
+    Ok(0)
 }
 
 
 // YOUR JOB: 实现sys_set_priority，为任务添加优先级
-pub fn sys_set_priority(prio: isize) -> isize {
+pub fn sys_set_priority(prio: isize) -> SysResult {
     if prio<=1{
-        return -1
+        return Err(Errno::EINVAL)
     }
     let task = current_task().unwrap();
-    // find a child process
 
     // ---- access current TCB exclusively
     let mut inner = task.inner_exclusive_access();
     inner.priority=prio as usize;
-    return prio ;
+    return Ok(prio as usize);
 }
 
 
@@ -141,106 +215,258 @@ pub fn sys_set_priority(prio: isize) -> isize {
 //
 // YOUR JOB: 实现 sys_spawn 系统调用
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC 
-pub fn sys_spawn(path: *const u8) -> isize {
-    let token = current_user_token();
-    let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(),OpenFlags::RDONLY) {
-        let data=inode.read_all();
-        let parent = current_task().unwrap();
-        let new_task=Arc::new( TaskControlBlock::new(data.as_slice(),Some(&parent)));
-        let new_pid = new_task.pid.0;
-        add_task(new_task.clone());
-        parent.inner_exclusive_access().children.push(new_task);
-        println!("[kernel] Spawned task {}, path={}", new_pid,path);
-        new_pid as isize
-    } else {
-        println!("[kernel] Spawn failed, path={}", path);
-        -1
-    }
+pub fn sys_spawn(path: *const u8, args: *const usize) -> SysResult {
+    let (path, args_vec) = translated_path_args(path, args)?;
+    let parent = current_process();
+    let cwd = parent.inner_exclusive_access().cwd.clone();
+    let inode = match open_file(&cwd, path.as_str(), OpenFlags::RDONLY) {
+        Ok(inode) => inode,
+        Err(err) => {
+            println!("[kernel] Spawn failed, path={}", path);
+            return Err(err);
+        }
+    };
+    let data=inode.read_all();
+    let new_process = match ProcessControlBlock::new(data.as_slice(), Some(&parent), args_vec) {
+        Ok(process) => process,
+        Err(err) => {
+            println!("[kernel] Spawn failed, path={}", path);
+            return Err(err);
+        }
+    };
+    let new_pid = new_process.getpid();
+    parent.inner_exclusive_access().children.push(new_process);
+    println!("[kernel] Spawned process {}, path={}", new_pid,path);
+    Ok(new_pid)
 }
 
+/// mmap flag: stores are written back to the file
+const MAP_SHARED: usize = 0x01;
+/// mmap flag: stores stay private to the process
+const MAP_PRIVATE: usize = 0x02;
+/// mmap flag: the area is not backed by a file
+const MAP_ANONYMOUS: usize = 0x20;
+
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
-pub fn sys_mmap(_start: usize, _len: usize, mut _port: usize) -> isize {
-    let task = current_task().unwrap();
+/// Map [_start, _start + _len), backed by the file `fd` from `offset` unless `flags`
+/// contains MAP_ANONYMOUS or neither MAP_SHARED nor MAP_PRIVATE
+pub fn sys_mmap(
+    _start: usize,
+    _len: usize,
+    mut _port: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    let process = current_process();
 
-    // ---- access current TCB exclusively
-    let mut task_inner = task.inner_exclusive_access();
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
     // println!("_start={:#x},_len={:#x},_port={}", _start, _len, _port);
     let start_va = VirtAddr::from(_start);
     if !start_va.aligned() {
-        return -1;
+        return Err(Errno::EINVAL);
     }
     _port = _port << 1;//第0位没有使用
     if (_port & !0x07) != 0 {
-        return -1;
+        return Err(Errno::EINVAL);
     }
     if (_port & 0x07) == 0 {
-        return -1;
+        return Err(Errno::EINVAL);
     }
     let end_va: VirtAddr = (_start + _len).into();
 
-    if task_inner.memory_set.is_mapped(start_va, end_va) {
+    if inner.memory_set.is_mapped(start_va, end_va) {
         println!("error already mapped");
-        return -1;
+        return Err(Errno::EINVAL);
     }
     let perm = MapPermission::from_bits_truncate(_port as u8);
-    task_inner.memory_set.insert_framed_area(_start.into(),
-                                          (_start + _len).into(),
-                                          perm | MapPermission::U)  ;
+    if flags & MAP_ANONYMOUS != 0 || flags & (MAP_SHARED | MAP_PRIVATE) == 0 {
+        inner.memory_set.insert_lazy_area(_start.into(),
+                                              (_start + _len).into(),
+                                              perm | MapPermission::U)  ;
+        return Ok(0);
+    }
+    if offset % PAGE_SIZE != 0 {
+        return Err(Errno::EINVAL);
+    }
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(fd)) => fd.file.clone(),
+        _ => return Err(Errno::EBADF),
+    };
+    let inode = match file.inode() {
+        Some(inode) => inode,
+        None => return Err(Errno::EACCES),
+    };
+    let shared = flags & MAP_SHARED != 0;
+    if !file.readable() || (shared && perm.contains(MapPermission::W) && !file.writable()) {
+        return Err(Errno::EACCES);
+    }
+    inner.memory_set.insert_file_area(start_va,
+                                          end_va,
+                                          perm | MapPermission::U,
+                                          inode,
+                                          offset,
+                                          shared);
     if get_current_pid() == 27 {
-        // println!("memory_set={:?}", task_inner.memory_set);
+        // println!("memory_set={:?}", inner.memory_set);
         // println!("port={:#x},perm={:?},perm2={:#x}", _port, perm, perm2.bits());
     }
-    0
+    Ok(0)
 }
 
-pub fn sys_munmap(_start: usize, _len: usize) -> isize {
+pub fn sys_munmap(_start: usize, _len: usize) -> SysResult {
     let start_va = VirtAddr::from(_start);
     let end_va: VirtAddr = (_start + _len).into();
-    let task = current_task().unwrap();
+    let process = current_process();
 
-    // ---- access current TCB exclusively
-    let mut tcb = task.inner_exclusive_access();
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
     if !start_va.aligned() {
-        return -1;
+        return Err(Errno::EINVAL);
+    }
+    if !inner.memory_set.is_mapped(start_va, end_va) {
+        return Err(Errno::EINVAL);
     }
-    if !tcb.memory_set.is_mapped(start_va, end_va) {
-        return -1;
+    // the heap only shrinks by sys_brk, its first page counts even when it is empty
+    let heap_start = VirtAddr::from(inner.base_size).floor();
+    let heap_end = VirtAddr::from(inner.program_brk).ceil().max(VirtPageNum(heap_start.0 + 1));
+    if start_va.floor() < heap_end && heap_start < end_va.ceil() {
+        return Err(Errno::EINVAL);
     }
     // println!("munmap start_va={:#x},end_va={:#x}", start_va.0, end_va.0);
     if get_current_pid() == 27 {
-        // println!("unmap before memory_set={:?}", tcb.memory_set);
+        // println!("unmap before memory_set={:?}", inner.memory_set);
         // println!("port={:#x},perm={:?},perm2={:#x}", _port, perm, perm2.bits());
     }
-    if !tcb.memory_set.remove_framed_area(start_va,
+    if !inner.memory_set.remove_framed_area(start_va,
                                           end_va) {
-        return -1;
+        return Err(Errno::EINVAL);
     }
     if get_current_pid() == 27 {
-        // println!("unmap after memory_set={:?}", tcb.memory_set);
+        // println!("unmap after memory_set={:?}", inner.memory_set);
         // println!("port={:#x},perm={:?},perm2={:#x}", _port, perm, perm2.bits());
     }
     println!("munmap success");
-    0
+    Ok(0)
 }
 
-pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    let buffers = translated_byte_buffer(current_user_token(), ti as *mut u8, size_of::<TaskInfo>());
-    assert_eq!(1, buffers.len());
-    let mut ti = unsafe { (buffers[0].as_ptr() as *mut TaskInfo).as_mut().unwrap() };
-    let task = current_task().unwrap();
+/// Move the program break to `addr`, returns the new break,
+/// or the current one if `addr` is 0 or the heap can not be moved there
+pub fn sys_brk(addr: usize) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let heap_bottom = inner.base_size;
+    if addr >= heap_bottom
+        && inner
+            .memory_set
+            .resize_area(heap_bottom.into(), addr.into())
+    {
+        inner.program_brk = addr;
+    }
+    Ok(inner.program_brk)
+}
 
-    // ---- access current TCB exclusively
-    let mut tcb = task.inner_exclusive_access();
-    let time = get_time_milli() - tcb.first_start_time;
-    ti.status = TaskStatus::Running;
-    ti.time = time;
-    ti.syscall_times = tcb.syscall_times;
-    0
+/// Change the protection of [start, start + len), `prot` is encoded the same way as in `sys_mmap`
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    let start_va = VirtAddr::from(start);
+    if !start_va.aligned() {
+        return Err(Errno::EINVAL);
+    }
+    let prot = prot << 1; //第0位没有使用
+    if (prot & !0x0e) != 0 || prot == 0 {
+        return Err(Errno::EINVAL);
+    }
+    let end_va: VirtAddr = (start + len).into();
+    let perm = MapPermission::from_bits_truncate(prot as u8);
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner.memory_set.protect_area(start_va, end_va, perm) {
+        return Err(Errno::ENOMEM);
+    }
+    Ok(0)
+}
+
+pub fn sys_task_info(ti: *mut TaskInfo) -> SysResult {
+    let process = current_process();
+
+    // ---- access current PCB exclusively
+    let mut inner = process.inner_exclusive_access();
+    let time = get_time_milli() - inner.first_start_time;
+    let info = TaskInfo {
+        status: TaskStatus::Running,
+        syscall_times: inner.syscall_times,
+        time,
+    };
+    UserPtr::new(ti).write(&mut inner.memory_set, info)?;
+    Ok(0)
 }
 
 
 pub fn get_current_pid()->usize{
-    let task=current_task();
-    task.unwrap().pid.0
-}
\ No newline at end of file
+    current_process().getpid()
+}
+
+/// Send signal `signum` to process `pid`, signal 0 only checks that the process exists
+pub fn sys_kill(pid: usize, signum: usize) -> SysResult {
+    let process = pid2process(pid).ok_or(Errno::ESRCH)?;
+    if signum == 0 {
+        return Ok(0);
+    }
+    let signal = SignalFlags::from_signum(signum).ok_or(Errno::EINVAL)?;
+    process.inner_exclusive_access().signals.insert(signal);
+    wake_for_fatal_signal(&process);
+    Ok(0)
+}
+
+/// Set the action of signal `signum` to `action` unless it is null,
+/// the previous action is written to `old_action` unless it is null
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> SysResult {
+    let signal = SignalFlags::from_signum(signum).ok_or(Errno::EINVAL)?;
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let action = UserPtr::new(action);
+    let new_action = if action.is_null() {
+        None
+    } else {
+        if SignalFlags::uncatchable().contains(signal) {
+            return Err(Errno::EINVAL);
+        }
+        Some(action.read(&mut inner.memory_set)?)
+    };
+    let old_action = UserPtr::new(old_action);
+    if !old_action.is_null() {
+        let prev = inner.signal_actions.table[signum];
+        old_action.write(&mut inner.memory_set, prev)?;
+    }
+    if let Some(mut new_action) = new_action {
+        new_action.mask = SignalFlags::from_bits_truncate(new_action.mask.bits()) - SignalFlags::uncatchable();
+        inner.signal_actions.table[signum] = new_action;
+    }
+    Ok(0)
+}
+
+/// Replace the blocked signals with `mask` and return the previous mask,
+/// SIGKILL and SIGSTOP can not be blocked
+pub fn sys_sigprocmask(mask: u32) -> SysResult {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    inner.signal_mask = SignalFlags::from_bits_truncate(mask) - SignalFlags::uncatchable();
+    Ok(old_mask.bits() as usize)
+}
+
+/// Return from a signal handler to the interrupted code,
+/// the result is the restored a0 so that the trap handler keeps it
+pub fn sys_sigreturn() -> SysResult {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let backup = inner.trap_ctx_backup.take().ok_or(Errno::EINVAL)?;
+    inner.handling_sig = None;
+    *inner.get_trap_cx() = backup;
+    Ok(backup.x[10])
+}
@@ -0,0 +1,171 @@
+//! Synchronization syscalls for the threads of a process
+
+use crate::errno::{Errno, SysResult};
+use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
+use crate::task::{current_killed, current_process, current_tid};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Put `item` into the first free slot of `list`, returns its id
+fn insert_into_list<T>(list: &mut Vec<Option<T>>, item: T) -> usize {
+    if let Some(id) = list.iter().position(|slot| slot.is_none()) {
+        list[id] = Some(item);
+        id
+    } else {
+        list.push(Some(item));
+        list.len() - 1
+    }
+}
+
+/// The object with `id` in `list`, EINVAL if there is none
+fn get_from_list<T: Clone>(list: &[Option<T>], id: usize) -> SysResult<T> {
+    list.get(id).cloned().flatten().ok_or(Errno::EINVAL)
+}
+
+/// Create a mutex, which blocks the waiting threads if `blocking` is set
+/// and yields the CPU in a loop otherwise, returns its id
+pub fn sys_mutex_create(blocking: bool) -> SysResult {
+    let mutex: Arc<dyn Mutex> = if blocking {
+        Arc::new(MutexBlocking::new())
+    } else {
+        Arc::new(MutexSpin::new())
+    };
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_into_list(&mut process_inner.mutex_list, mutex);
+    process_inner.mutex_detector.add_resource(id, 1);
+    Ok(id)
+}
+
+/// EDEADLOCKED instead of waiting if deadlock detection is enabled and waiting would deadlock
+pub fn sys_mutex_lock(mutex_id: usize) -> SysResult {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let mutex = get_from_list(&process_inner.mutex_list, mutex_id)?;
+    if !process_inner.mutex_detector.request(tid, mutex_id) && process_inner.deadlock_detect {
+        return Err(Errno::EDEADLOCKED);
+    }
+    drop(process_inner);
+    // do not keep the process alive while blocked
+    drop(process);
+    mutex.lock();
+    // woken up by a fatal signal without the mutex
+    if current_killed() {
+        return Ok(0);
+    }
+    current_process()
+        .inner_exclusive_access()
+        .mutex_detector
+        .acquire(tid, mutex_id);
+    Ok(0)
+}
+
+/// EPERM if the mutex is not locked
+pub fn sys_mutex_unlock(mutex_id: usize) -> SysResult {
+    let tid = current_tid();
+    let process = current_process();
+    let mutex = get_from_list(&process.inner_exclusive_access().mutex_list, mutex_id)?;
+    if !mutex.unlock() {
+        return Err(Errno::EPERM);
+    }
+    process.inner_exclusive_access().mutex_detector.release(tid, mutex_id);
+    Ok(0)
+}
+
+/// Create a semaphore with `res_count` resources, returns its id
+pub fn sys_semaphore_create(res_count: usize) -> SysResult {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = insert_into_list(
+        &mut process_inner.semaphore_list,
+        Arc::new(Semaphore::new(res_count)),
+    );
+    process_inner.semaphore_detector.add_resource(id, res_count);
+    Ok(id)
+}
+
+pub fn sys_semaphore_up(sem_id: usize) -> SysResult {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = get_from_list(&process_inner.semaphore_list, sem_id)?;
+    process_inner.semaphore_detector.release(tid, sem_id);
+    drop(process_inner);
+    sem.up();
+    Ok(0)
+}
+
+/// EDEADLOCKED instead of waiting if deadlock detection is enabled and waiting would deadlock
+pub fn sys_semaphore_down(sem_id: usize) -> SysResult {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = get_from_list(&process_inner.semaphore_list, sem_id)?;
+    if !process_inner.semaphore_detector.request(tid, sem_id) && process_inner.deadlock_detect {
+        return Err(Errno::EDEADLOCKED);
+    }
+    drop(process_inner);
+    // do not keep the process alive while blocked
+    drop(process);
+    sem.down();
+    // woken up by a fatal signal without a resource
+    if current_killed() {
+        return Ok(0);
+    }
+    current_process()
+        .inner_exclusive_access()
+        .semaphore_detector
+        .acquire(tid, sem_id);
+    Ok(0)
+}
+
+/// Create a condition variable, returns its id
+pub fn sys_condvar_create(_arg: usize) -> SysResult {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    Ok(insert_into_list(
+        &mut process_inner.condvar_list,
+        Arc::new(Condvar::new()),
+    ))
+}
+
+pub fn sys_condvar_signal(condvar_id: usize) -> SysResult {
+    let process = current_process();
+    let condvar = get_from_list(&process.inner_exclusive_access().condvar_list, condvar_id)?;
+    drop(process);
+    condvar.signal();
+    Ok(0)
+}
+
+/// Unlock the mutex, wait for a signal and lock it again like [`sys_mutex_lock`],
+/// EPERM if the mutex is not locked
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult {
+    let tid = current_tid();
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = get_from_list(&process_inner.condvar_list, condvar_id)?;
+    let mutex = get_from_list(&process_inner.mutex_list, mutex_id)?;
+    drop(process_inner);
+    if !mutex.unlock() {
+        return Err(Errno::EPERM);
+    }
+    process.inner_exclusive_access().mutex_detector.release(tid, mutex_id);
+    drop(process);
+    condvar.wait();
+    if current_killed() {
+        return Ok(0);
+    }
+    sys_mutex_lock(mutex_id)
+}
+
+/// Enable deadlock detection for the current process if `enabled` is 1, disable it if 0
+pub fn sys_enable_deadlock_detect(enabled: usize) -> SysResult {
+    let process = current_process();
+    process.inner_exclusive_access().deadlock_detect = match enabled {
+        0 => false,
+        1 => true,
+        _ => return Err(Errno::EINVAL),
+    };
+    Ok(0)
+}
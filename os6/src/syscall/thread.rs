@@ -0,0 +1,76 @@
+//! Thread management syscalls
+
+use crate::errno::{Errno, SysResult};
+use crate::mm::{kernel_token, UserPtr};
+use crate::task::{add_task, current_task, current_tid, TaskControlBlock};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::sync::Arc;
+
+/// Create a thread of the current process starting at `entry` with `arg` in a0,
+/// returns the tid of the new thread
+pub fn sys_thread_create(entry: usize, arg: usize) -> SysResult {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // create a new thread with its own user stack and trap context
+    let new_task = Arc::new(TaskControlBlock::new(Arc::clone(&process), true)?);
+    let new_task_inner = new_task.inner_exclusive_access();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        new_task_res.ustack_top(),
+        kernel_token(),
+        new_task.kernel_stack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    drop(new_task_inner);
+
+    let mut process_inner = process.inner_exclusive_access();
+    // add new thread to current process
+    let tasks = &mut process_inner.tasks;
+    while tasks.len() < new_task_tid + 1 {
+        tasks.push(None);
+    }
+    tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    // add new task to scheduler
+    add_task(new_task);
+    Ok(new_task_tid)
+}
+
+pub fn sys_gettid() -> SysResult {
+    Ok(current_tid())
+}
+
+/// Wait for thread `tid` of the current process to exit, its exit code is written
+/// to `exit_code_ptr` unless it is null, returns `tid`.
+///
+/// EAGAIN if the thread has not exited yet, ESRCH if there is no such thread
+/// and EDEADLK if it is the current thread
+pub fn sys_waittid(tid: usize, exit_code_ptr: *mut i32) -> SysResult {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    let mut process_inner = process.inner_exclusive_access();
+    // a thread cannot wait for itself
+    if task_inner.res.as_ref().unwrap().tid == tid {
+        return Err(Errno::EDEADLK);
+    }
+    let waited_task = match process_inner.tasks.get(tid) {
+        Some(Some(waited_task)) => waited_task.clone(),
+        // waited thread does not exist
+        _ => return Err(Errno::ESRCH),
+    };
+    let exit_code = waited_task
+        .inner_exclusive_access()
+        .exit_code
+        .ok_or(Errno::EAGAIN)?;
+    let exit_code_ptr = UserPtr::new(exit_code_ptr);
+    if !exit_code_ptr.is_null() {
+        exit_code_ptr.write(&mut process_inner.memory_set, exit_code)?;
+    }
+    // dealloc the exited thread
+    process_inner.tasks[tid] = None;
+    Ok(tid)
+}
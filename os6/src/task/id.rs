@@ -0,0 +1,220 @@
+//! Allocation of pids, tids and kernel stacks.
+//!
+//! The position of a KernelStack in kernel space is determined by its id,
+//! the user stack and trap context of a thread by its tid.
+
+use super::ProcessControlBlock;
+use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE, USER_STACK_TOP};
+use crate::errno::SysResult;
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Id allocator using stack allocation
+pub struct RecycleAllocator {
+    /// A new id to be assigned
+    current: usize,
+    /// Recycled id sequence
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    pub fn new() -> Self {
+        RecycleAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+lazy_static! {
+    /// Pid allocator instance through lazy_static!
+    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+    /// Kernel stack allocator, every thread has its own kernel stack
+    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+
+/// Abstract structure of PID
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        //println!("drop pid {}", self.0);
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// Return (bottom, top) of a kernel stack in kernel space.
+pub fn kernel_stack_position(kstack_id: usize) -> (usize, usize) {
+    let top = TRAMPOLINE - kstack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+    let bottom = top - KERNEL_STACK_SIZE;
+    (bottom, top)
+}
+
+/// Kernel stack of a thread
+pub struct KernelStack(pub usize);
+
+/// Allocate a kernel stack, ENOMEM if there are no frames for it
+pub fn kstack_alloc() -> SysResult<KernelStack> {
+    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+    let kernel_stack = KernelStack(kstack_id);
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    let mapped = KERNEL_SPACE.exclusive_access().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    // the id goes back with kernel_stack on failure
+    mapped?;
+    Ok(kernel_stack)
+}
+
+impl KernelStack {
+    #[allow(unused)]
+    /// Push a variable of type T into the top of the KernelStack and return its raw pointer
+    pub fn push_on_top<T>(&self, value: T) -> *mut T
+    where
+        T: Sized,
+    {
+        let kernel_stack_top = self.get_top();
+        let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
+        unsafe {
+            *ptr_mut = value;
+        }
+        ptr_mut
+    }
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Resources of a thread in the address space of its process:
+/// the tid, the user stack and the trap context
+pub struct TaskUserRes {
+    pub tid: usize,
+    pub process: Weak<ProcessControlBlock>,
+}
+
+fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT - tid * PAGE_SIZE
+}
+
+fn ustack_bottom_from_tid(tid: usize) -> usize {
+    USER_STACK_TOP - USER_STACK_SIZE - tid * (USER_STACK_SIZE + PAGE_SIZE)
+}
+
+impl TaskUserRes {
+    /// Allocate a tid in `process`, with `alloc_user_res` also its user stack and trap context,
+    /// which already exist in an address space copied by fork.
+    /// ENOMEM if there is no frame for the trap context
+    pub fn new(process: Arc<ProcessControlBlock>, alloc_user_res: bool) -> SysResult<Self> {
+        let tid = process.inner_exclusive_access().alloc_tid();
+        let task_user_res = Self {
+            tid,
+            process: Arc::downgrade(&process),
+        };
+        if alloc_user_res {
+            task_user_res.alloc_user_res()?;
+        }
+        Ok(task_user_res)
+    }
+
+    /// Map the user stack, whose frames are allocated on demand, and the trap context
+    pub fn alloc_user_res(&self) -> SysResult<()> {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        self.map_user_res(&mut process_inner.memory_set)
+    }
+
+    /// Like `alloc_user_res`, but into `memory_set`, which exec is about to install
+    pub fn map_user_res(&self, memory_set: &mut MemorySet) -> SysResult<()> {
+        let ustack_bottom = ustack_bottom_from_tid(self.tid);
+        memory_set.insert_lazy_area(
+            ustack_bottom.into(),
+            self.ustack_top().into(),
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
+        memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            (trap_cx_bottom + PAGE_SIZE).into(),
+            MapPermission::R | MapPermission::W,
+        )
+    }
+
+    fn dealloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let ustack_bottom_va: VirtAddr = ustack_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(ustack_bottom_va.into());
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .remove_area_with_start_vpn(trap_cx_bottom_va.into());
+        process_inner.dealloc_tid(self.tid);
+    }
+
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.inner_exclusive_access();
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .translate(trap_cx_bottom_va.into())
+            .unwrap()
+            .ppn()
+    }
+
+    pub fn ustack_top(&self) -> usize {
+        ustack_bottom_from_tid(self.tid) + USER_STACK_SIZE
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        self.dealloc_user_res();
+    }
+}
@@ -0,0 +1,40 @@
+//! Implementation of [`Mailbox`]
+//!
+//! Every process receives messages from the others in its own mailbox,
+//! neither reading nor writing ever blocks.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Number of messages a mailbox holds
+pub const MAIL_SLOTS: usize = 16;
+/// Longer messages are truncated
+pub const MAIL_SIZE: usize = 256;
+
+/// A bounded FIFO queue of messages
+pub struct Mailbox {
+    mails: VecDeque<Vec<u8>>,
+}
+
+impl Mailbox {
+    pub fn new() -> Self {
+        Self {
+            mails: VecDeque::new(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.mails.is_empty()
+    }
+    pub fn is_full(&self) -> bool {
+        self.mails.len() == MAIL_SLOTS
+    }
+    /// Append a message, the caller makes sure the mailbox is not full
+    pub fn push(&mut self, mail: Vec<u8>) {
+        assert!(!self.is_full());
+        self.mails.push_back(mail);
+    }
+    /// Take the oldest message
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.mails.pop_front()
+    }
+}
@@ -4,9 +4,10 @@
 //! Other CPU process monitoring functions are in Processor.
 
 
-use super::TaskControlBlock;
+use super::{ProcessControlBlock, TaskControlBlock};
+use crate::mm::MemorySet;
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use lazy_static::*;
@@ -27,6 +28,10 @@ impl TaskManager {
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
         self.ready_queue.push(task);
     }
+    /// Remove a thread of an exited process from the ready queue
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.ready_queue.retain(|t| !Arc::ptr_eq(t, task));
+    }
     /// Take a process out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
         if self.ready_queue.is_empty() {
@@ -49,12 +54,70 @@ lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
     pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
         unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// Every process which has not exited, indexed by pid
+    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+    /// Pid of the process which gave up the last evicted page
+    static ref SWAP_HAND: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
     TASK_MANAGER.exclusive_access().add(task);
 }
 
+pub fn remove_task(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().remove(task);
+}
+
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+/// Find the process with `pid` unless it has exited
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    PID2PCB.exclusive_access().get(&pid).cloned()
+}
+
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.exclusive_access().insert(pid, process);
+}
+
+/// Evict one user page of any process to free a frame,
+/// returns false if there is nothing left to evict or the swap region is full.
+///
+/// The processes take turns by pid, each one picks its page with its own clock.
+/// `current` is the memory set borrowed by the caller, it stands in for the
+/// process whose PCB cannot be borrowed, or comes last if there is none
+pub fn swap_out_any(current: &mut MemorySet) -> bool {
+    let hand = *SWAP_HAND.exclusive_access();
+    let processes: Vec<(usize, Arc<ProcessControlBlock>)> = {
+        let pid2pcb = PID2PCB.exclusive_access();
+        pid2pcb
+            .range(hand + 1..)
+            .chain(pid2pcb.range(..=hand))
+            .map(|(pid, process)| (*pid, Arc::clone(process)))
+            .collect()
+    };
+    let mut current_tried = false;
+    for (pid, process) in processes {
+        let evicted = match process.try_inner_exclusive_access() {
+            Some(mut inner) => inner.memory_set.swap_out_one(),
+            None if !current_tried => {
+                current_tried = true;
+                current.swap_out_one()
+            }
+            None => false,
+        };
+        if evicted {
+            *SWAP_HAND.exclusive_access() = pid;
+            return true;
+        }
+    }
+    !current_tried && current.swap_out_one()
+}
+
+pub fn remove_from_pid2process(pid: usize) {
+    if PID2PCB.exclusive_access().remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
@@ -10,27 +10,38 @@
 //! might not be what you expect.
 
 mod context;
+mod id;
+mod mailbox;
 mod manager;
-mod pid;
+mod process;
 mod processor;
+mod signal;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod wait_queue;
 
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use id::TaskUserRes;
 use lazy_static::*;
-use manager::fetch_task;
+use manager::{fetch_task, remove_task};
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
-pub use manager::add_task;
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
+pub use mailbox::{Mailbox, MAIL_SIZE};
+pub use manager::{add_task, insert_into_pid2process, pid2process, remove_from_pid2process, swap_out_any};
+pub use process::ProcessControlBlock;
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_process, current_task, current_tid, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, run_tasks, schedule, take_current_task,
 };
-use crate::fs::{open_file, OpenFlags};
+pub use signal::{SignalAction, SignalActions, SignalFlags, MAX_SIG};
+pub use wait_queue::WaitQueue;
+use crate::fs::{open_file, OpenFlags, ROOT_INODE};
 
 /// Make current task suspended and switch to the next task
 pub fn suspend_current_and_run_next() {
@@ -53,38 +64,138 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
-/// Exit current task, recycle process resources and switch to the next task
+/// Make current task blocked and switch to the next task,
+/// it has been put into a [`WaitQueue`] which wakes it up
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Blocked;
+    drop(task_inner);
+    schedule(task_cx_ptr);
+}
+
+/// Wake up the blocked threads of `process` if a pending signal is going to terminate it.
+///
+/// They see [`current_killed`], leave the queue they were waiting in and
+/// return to user mode, where [`handle_signals`] ends the process
+pub fn wake_for_fatal_signal(process: &ProcessControlBlock) {
+    let inner = process.inner_exclusive_access();
+    if !inner.fatal_signal_pending() {
+        return;
+    }
+    let tasks: Vec<Arc<TaskControlBlock>> = inner.tasks.iter().flatten().cloned().collect();
+    drop(inner);
+    for task in tasks {
+        wakeup_task(task);
+    }
+}
+
+/// Whether the current thread has to stop waiting because its process is being killed
+pub fn current_killed() -> bool {
+    current_process().inner_exclusive_access().fatal_signal_pending()
+}
+
+/// Put a blocked task back into the ready queue, returns false
+/// if it is not blocked any more because its process has exited
+pub fn wakeup_task(task: Arc<TaskControlBlock>) -> bool {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.task_status != TaskStatus::Blocked {
+        return false;
+    }
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+    true
+}
+
+/// Exit current thread and switch to the next task,
+/// the whole process exits with the main thread
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, false);
+}
+
+/// Exit the process of the current thread with all its threads and switch to the next task
+pub fn exit_current_process_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
+
+fn exit_current(exit_code: i32, whole_process: bool) {
     // take from Processor
     let task = take_current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
     // **** access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
+    let mut task_inner = task.inner_exclusive_access();
+    let tid = task_inner.res.as_ref().unwrap().tid;
     // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
+    task_inner.task_status = TaskStatus::Zombie;
+    // Record exit code
+    task_inner.exit_code = Some(exit_code);
+    // release the user stack and trap context, the kernel stack is still in use
+    // and it is deallocated when the thread is waited for
+    task_inner.res = None;
+    drop(task_inner);
+    // **** release current TCB
+    drop(task);
+
+    // the exited thread is no longer part of deadlock detection
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.mutex_detector.remove_thread(tid);
+    process_inner.semaphore_detector.remove_thread(tid);
+    drop(process_inner);
+
+    if tid == 0 || whole_process {
+        exit_process(&process, exit_code);
+    }
+    drop(process);
+    // we do not have to save task context
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
+
+/// Turn `process` into a zombie and recycle its resources except the PCB
+fn exit_process(process: &Arc<ProcessControlBlock>, exit_code: i32) {
+    // ++++++ access process PCB exclusively
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.is_zombie = true;
     // Record exit code
-    inner.exit_code = exit_code;
+    process_inner.exit_code = exit_code;
+    // no more signals can be sent to it
+    remove_from_pid2process(process.getpid());
     // do not move to its parent but under initproc
 
-    // ++++++ access initproc TCB exclusively
+    // ++++++ access initproc PCB exclusively
     {
         let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
+        for child in process_inner.children.iter() {
             child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
             initproc_inner.children.push(child.clone());
         }
     }
-    // ++++++ release parent PCB
+    // ++++++ release initproc PCB
+    process_inner.children.clear();
+
+    // the other threads never run again, take their user resources,
+    // they have to be released before the whole memory_set
+    let mut recycle_res = Vec::<TaskUserRes>::new();
+    for task in process_inner.tasks.iter().flatten() {
+        remove_task(task);
+        let mut task_inner = task.inner_exclusive_access();
+        if let Some(res) = task_inner.res.take() {
+            task_inner.task_status = TaskStatus::Zombie;
+            recycle_res.push(res);
+        }
+    }
+    drop(process_inner);
+    // dropping the resources accesses the process PCB
+    recycle_res.clear();
 
-    inner.children.clear();
+    let mut process_inner = process.inner_exclusive_access();
     // deallocate user space
-    inner.memory_set.recycle_data_pages();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
-    drop(task);
-    // we do not have to save task context
-    let mut _unused = TaskContext::zero_init();
-    schedule(&mut _unused as *mut _);
+    process_inner.memory_set.recycle_data_pages();
+    // drop file descriptors
+    process_inner.fd_table.clear();
+    // ++++++ release process PCB
 }
 
 lazy_static! {
@@ -92,14 +203,109 @@ lazy_static! {
     ///
     /// the name "initproc" may be changed to any other app name like "usertests",
     /// but we have user_shell, so we don't need to change it.
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
-        let inode = open_file("ch6b_initproc", OpenFlags::RDONLY).unwrap();
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
+        let inode = open_file(&ROOT_INODE, "ch6b_initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        TaskControlBlock::new(v.as_slice(),None)
-    });
+        ProcessControlBlock::new(v.as_slice(), None, Vec::new()).unwrap()
+    };
 }
 
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    // INITPROC must be referenced at least once so that it can be initialized
+    // through lazy_static, which also puts its main thread into the ready queue
+    let _initproc = INITPROC.clone();
+}
+
+/// Deliver the pending signals of the current process before the current thread
+/// returns to user mode. It does not come back if a signal terminates the process
+pub fn handle_signals() {
+    loop {
+        check_pending_signals();
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        if let Some(signum) = inner.killed {
+            drop(inner);
+            drop(process);
+            println!("[kernel] Application killed by signal {}.", signum);
+            exit_current_process_and_run_next(-(signum as i32));
+            return;
+        }
+        if !inner.frozen {
+            break;
+        }
+        drop(inner);
+        drop(process);
+        // stopped, wait for SIGCONT or SIGKILL
+        suspend_current_and_run_next();
+    }
+}
+
+/// Take the actions of the deliverable signals of the current process, in the order of signal number.
+///
+/// Only one user handler runs at a time on a thread, a signal caught by a handler stays pending
+/// until the running handler returns, as do the signals masked by the running handler.
+fn check_pending_signals() {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let process = task.process.upgrade().unwrap();
+    let mut inner = process.inner_exclusive_access();
+    for signum in 1..=MAX_SIG {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if !inner.signals.contains(signal) || inner.signal_mask.contains(signal) {
+            continue;
+        }
+        // a stopped process only reacts to SIGKILL and SIGCONT
+        if inner.frozen && !(SignalFlags::SIGKILL | SignalFlags::SIGCONT).contains(signal) {
+            continue;
+        }
+        let action = inner.signal_actions.table[signum];
+        if let Some(handling) = task_inner.handling_sig {
+            let blocked = inner.signal_actions.table[handling].mask | SignalFlags::from_signum(handling).unwrap();
+            if blocked.contains(signal) || action.handler != 0 {
+                continue;
+            }
+        }
+        inner.signals.remove(signal);
+        if signal == SignalFlags::SIGKILL {
+            inner.killed = Some(signum);
+            return;
+        }
+        if signal == SignalFlags::SIGSTOP {
+            inner.frozen = true;
+            continue;
+        }
+        if signal == SignalFlags::SIGCONT {
+            inner.frozen = false;
+        }
+        if action.handler != 0 {
+            // run the handler with the signal number in a0 and come back by sigreturn
+            let trap_cx = task_inner.get_trap_cx();
+            task_inner.trap_ctx_backup = Some(*trap_cx);
+            trap_cx.sepc = action.handler;
+            trap_cx.x[10] = signum;
+            task_inner.handling_sig = Some(signum);
+        } else if !signal.ignored_by_default() {
+            inner.killed = Some(signum);
+            return;
+        }
+    }
 }
 
+/// Raise `signal` for a fault of the current thread.
+/// The faulting instruction would run again, so the process is killed
+/// unless a handler of the signal can run right away
+pub fn current_raise_fault(signal: SignalFlags) {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    let process = task.process.upgrade().unwrap();
+    let mut inner = process.inner_exclusive_access();
+    let signum = signal.bits().trailing_zeros() as usize;
+    if inner.signal_actions.table[signum].handler != 0
+        && !inner.signal_mask.contains(signal)
+        && task_inner.handling_sig.is_none()
+    {
+        inner.signals.insert(signal);
+    } else {
+        inner.killed = Some(signum);
+    }
+}
@@ -0,0 +1,371 @@
+//! Types related to process management & Functions for completely changing PCB
+
+use super::id::RecycleAllocator;
+use super::{add_task, insert_into_pid2process, pid_alloc, PidHandle, TaskControlBlock};
+use super::{Mailbox, SignalActions, SignalFlags, MAX_SIG};
+use crate::config::MAX_SYSCALL_NUM;
+use crate::errno::SysResult;
+use crate::fs::{FileDescriptor, Stdin, Stdout, ROOT_INODE};
+use crate::mm::{MemorySet, UserPtr, UserSlice, KERNEL_SPACE};
+use crate::sync::{Condvar, DeadlockDetector, Mutex, Semaphore, UPSafeCell};
+use crate::timer::get_time_milli;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+use easy_fs::Inode;
+
+/// Process control block structure
+///
+/// Directly save the contents that will not change during running
+pub struct ProcessControlBlock {
+    // immutable
+    /// Process identifier
+    pub pid: PidHandle,
+    // mutable
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// Structure containing the state shared by the threads of a process
+pub struct ProcessControlBlockInner {
+    /// Set when the main thread exits, until the parent waits for it
+    pub is_zombie: bool,
+    /// Application address space
+    pub memory_set: MemorySet,
+    /// Application data can only appear in areas
+    /// where the application address space is lower than base_size
+    pub base_size: usize,
+    /// Program break, the heap spans [base_size, program_brk)
+    pub program_brk: usize,
+    /// Parent process of the current process.
+    /// Weak will not affect the reference count of the parent
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// A vector containing PCBs of all child processes of the current process
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// Exit code of the main thread
+    pub exit_code: i32,
+    pub fd_table: Vec<Option<FileDescriptor>>,
+    /// Current working directory, relative paths are resolved from here
+    pub cwd: Arc<Inode>,
+    /// Threads indexed by tid, an exited thread stays until it is waited for
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    pub task_res_allocator: RecycleAllocator,
+    /// Sync objects of the threads, indexed by their ids
+    pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// Whether mutex_lock and semaphore_down fail instead of deadlocking
+    pub deadlock_detect: bool,
+    pub mutex_detector: DeadlockDetector,
+    pub semaphore_detector: DeadlockDetector,
+
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub first_start_time: usize,
+
+    /// Signals sent to the process and not delivered yet
+    pub signals: SignalFlags,
+    /// Signals blocked by sigprocmask
+    pub signal_mask: SignalFlags,
+    pub signal_actions: SignalActions,
+    /// The signal which terminates the process when a thread returns to user mode
+    pub killed: Option<usize>,
+    /// Stopped by SIGSTOP until SIGCONT arrives
+    pub frozen: bool,
+    /// Messages sent by mail_write
+    pub mailbox: Mailbox,
+}
+
+impl ProcessControlBlockInner {
+    #[allow(unused)]
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len())
+            .find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+    /// Whether the main thread is the only thread which has not exited,
+    /// fork and exec only support such a process
+    pub fn is_single_threaded(&self) -> bool {
+        self.tasks
+            .iter()
+            .skip(1)
+            .flatten()
+            .all(|task| task.inner_exclusive_access().res.is_none())
+    }
+    /// Whether a pending signal is going to terminate the process, the way
+    /// `check_pending_signals` delivers it without a user handler
+    pub fn fatal_signal_pending(&self) -> bool {
+        self.killed.is_some()
+            || (1..=MAX_SIG).any(|signum| {
+                let signal = SignalFlags::from_signum(signum).unwrap();
+                if !self.signals.contains(signal) || signal == SignalFlags::SIGSTOP {
+                    return false;
+                }
+                signal == SignalFlags::SIGKILL
+                    || (!self.frozen
+                        && !self.signal_mask.contains(signal)
+                        && self.signal_actions.table[signum].handler == 0
+                        && !signal.ignored_by_default())
+            })
+    }
+}
+
+impl ProcessControlBlock {
+    /// Get the mutex to get the RefMut ProcessControlBlockInner
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Like `inner_exclusive_access`, but None if the inner is borrowed up the call stack
+    pub fn try_inner_exclusive_access(&self) -> Option<RefMut<'_, ProcessControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
+
+    /// Create a new process and put its main thread into the ready queue
+    ///
+    /// Used for the creation of initproc and by spawn
+    pub fn new(
+        elf_data: &[u8],
+        parent: Option<&Arc<Self>>,
+        args: Vec<String>,
+    ) -> SysResult<Arc<Self>> {
+        // memory_set with elf program headers/trampoline/heap
+        let (memory_set, base_size, entry_point) = MemorySet::from_elf(elf_data)?;
+        // a spawned process starts in the working directory of its parent
+        let cwd = match parent {
+            Some(parent) => parent.inner_exclusive_access().cwd.clone(),
+            None => ROOT_INODE.clone(),
+        };
+        let process = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    base_size,
+                    program_brk: base_size,
+                    parent: parent.map(Arc::downgrade),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![
+                        // 0 -> stdin
+                        Some(FileDescriptor::new(Arc::new(Stdin), false)),
+                        // 1 -> stdout
+                        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+                        // 2 -> stderr
+                        Some(FileDescriptor::new(Arc::new(Stdout), false)),
+                    ],
+                    cwd,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                    mutex_list: Vec::new(),
+                    semaphore_list: Vec::new(),
+                    condvar_list: Vec::new(),
+                    deadlock_detect: false,
+                    mutex_detector: DeadlockDetector::new(),
+                    semaphore_detector: DeadlockDetector::new(),
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    first_start_time: get_time_milli(),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    killed: None,
+                    frozen: false,
+                    mailbox: Mailbox::new(),
+                })
+            },
+        });
+        // create the main thread with its user stack and trap context
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&process), true)?);
+        let task_inner = task.inner_exclusive_access();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let (user_sp, argv_base) =
+            push_args(&mut process.inner_exclusive_access().memory_set, ustack_top, &args);
+        let trap_cx = task_inner.get_trap_cx();
+        drop(task_inner);
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        process.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        add_task(task);
+        Ok(process)
+    }
+
+    /// Load a new elf to replace the original application address space and start execution
+    ///
+    /// Only supports a single threaded process, the original address space
+    /// is kept if `elf_data` is not a valid elf
+    pub fn exec(&self, elf_data: &[u8], args: Vec<String>) -> SysResult<()> {
+        // memory_set with elf program headers/trampoline/heap
+        let (mut memory_set, base_size, entry_point) = MemorySet::from_elf(elf_data)?;
+        // the main thread keeps its tid, map its user stack and trap context again
+        let task = self.inner_exclusive_access().get_task(0);
+        task.inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .map_user_res(&mut memory_set)?;
+
+        // **** access inner exclusively
+        let mut inner = self.inner_exclusive_access();
+        // substitute memory_set
+        inner.memory_set = memory_set;
+        // the new heap is empty
+        inner.base_size = base_size;
+        inner.program_brk = base_size;
+        // the handlers are gone with the old image, the mask is kept
+        inner.signal_actions = SignalActions::default();
+        // close the fds marked close-on-exec
+        for fd in inner.fd_table.iter_mut() {
+            if fd.as_ref().map_or(false, |fd| fd.cloexec) {
+                *fd = None;
+            }
+        }
+        // the sync objects are gone too
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.deadlock_detect = false;
+        inner.mutex_detector = DeadlockDetector::new();
+        inner.semaphore_detector = DeadlockDetector::new();
+        drop(inner);
+        // **** release inner
+
+        let mut task_inner = task.inner_exclusive_access();
+        let res = task_inner.res.as_ref().unwrap();
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let ustack_top = res.ustack_top();
+        task_inner.trap_cx_ppn = trap_cx_ppn;
+        task_inner.handling_sig = None;
+        task_inner.trap_ctx_backup = None;
+        // push arguments on user stack
+        let (user_sp, argv_base) =
+            push_args(&mut self.inner_exclusive_access().memory_set, ustack_top, &args);
+        // initialize trap_cx
+        let trap_cx = task_inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            task.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        Ok(())
+    }
+
+    /// Fork from parent to child and put the main thread of the child into the ready queue
+    ///
+    /// Only supports a single threaded process, ENOMEM if the child cannot be set up
+    pub fn fork(self: &Arc<Self>) -> SysResult<Arc<Self>> {
+        // ---- access parent PCB exclusively
+        let mut parent_inner = self.inner_exclusive_access();
+        // copy user space(include trap context and user stack of the main thread)
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set)?;
+        // clone all fds from parent to child
+        let new_fd_table: Vec<Option<FileDescriptor>> = parent_inner.fd_table.clone();
+        let child = Arc::new(Self {
+            pid: pid_alloc(),
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    base_size: parent_inner.base_size,
+                    program_brk: parent_inner.program_brk,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: new_fd_table,
+                    cwd: parent_inner.cwd.clone(),
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                    // the sync objects are not inherited
+                    mutex_list: Vec::new(),
+                    semaphore_list: Vec::new(),
+                    condvar_list: Vec::new(),
+                    deadlock_detect: false,
+                    mutex_detector: DeadlockDetector::new(),
+                    semaphore_detector: DeadlockDetector::new(),
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    first_start_time: get_time_milli(),
+                    // pending signals are not inherited
+                    signals: SignalFlags::empty(),
+                    signal_mask: parent_inner.signal_mask,
+                    signal_actions: parent_inner.signal_actions.clone(),
+                    killed: None,
+                    frozen: false,
+                    mailbox: Mailbox::new(),
+                })
+            },
+        });
+        drop(parent_inner);
+        // ---- release parent PCB
+
+        // the main thread of the child, its user stack and trap context are copied
+        // but it has its own kernel stack
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&child), false)?);
+        child.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        // add child
+        self.inner_exclusive_access().children.push(Arc::clone(&child));
+        // modify kernel_sp in trap_cx
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = task.kernel_stack.get_top();
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        add_task(task);
+        Ok(child)
+    }
+
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+}
+
+/// Copy `args` onto the user stack of `memory_set` below `user_sp`,
+/// followed by a null-terminated array of pointers to them.
+/// Returns the new user_sp and the address of that array (argv).
+/// The syscalls make sure the arguments fit in the fresh user stack
+fn push_args(memory_set: &mut MemorySet, mut user_sp: usize, args: &[String]) -> (usize, usize) {
+    user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+    let argv_base = user_sp;
+    let argv = UserPtr::new(argv_base as *const usize);
+    argv.add(args.len()).write(memory_set, 0).unwrap();
+    for (i, arg) in args.iter().enumerate() {
+        user_sp -= arg.len() + 1;
+        argv.add(i).write(memory_set, user_sp).unwrap();
+        let buffer = UserSlice::new(user_sp as *const u8, arg.len() + 1)
+            .writer(memory_set)
+            .unwrap();
+        for (byte_ref, byte) in buffer.into_iter().zip(arg.bytes().chain(Some(0))) {
+            unsafe {
+                *byte_ref = byte;
+            }
+        }
+    }
+    // make the user_sp aligned to 8B
+    user_sp -= user_sp % core::mem::size_of::<usize>();
+    (user_sp, argv_base)
+}
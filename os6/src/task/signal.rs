@@ -0,0 +1,97 @@
+//! Types related to signals
+//!
+//! A signal is delivered in [`crate::trap::trap_return()`] by
+//! [`super::handle_signals()`], a user handler runs on the interrupted
+//! user stack and goes back with `sys_sigreturn`.
+
+/// Signal numbers are in `1..=MAX_SIG`
+pub const MAX_SIG: usize = 31;
+
+bitflags! {
+    /// A set of signals, signal `signum` is the bit `1 << signum`
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
+        const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
+        const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
+        const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
+        const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
+        const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The set holding only signal `signum`, None if `signum` is not a signal
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            return None;
+        }
+        Self::from_bits(1 << signum)
+    }
+    /// Signals which can be neither caught nor blocked
+    pub fn uncatchable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+    /// Whether a single signal is ignored when it has no handler,
+    /// all the other signals terminate the task
+    pub fn ignored_by_default(&self) -> bool {
+        (Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH | Self::SIGCONT).contains(*self)
+    }
+}
+
+/// What to do when a signal is delivered
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// Address of the user handler, 0 for the default action
+    pub handler: usize,
+    /// Signals blocked while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: 0,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// The signal actions of a task, indexed by signal number
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
@@ -1,18 +1,16 @@
 //! Types related to task management & Functions for completely changing TCB
+//!
+//! A task is a thread, the state shared by the threads of a process is in
+//! [`super::ProcessControlBlock`].
 
-use super::TaskContext;
-use super::{pid_alloc, KernelStack, PidHandle};
-use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT};
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
+use super::id::TaskUserRes;
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use crate::errno::SysResult;
+use crate::mm::PhysPageNum;
 use crate::sync::UPSafeCell;
-use crate::trap::{trap_handler, TrapContext};
+use crate::trap::TrapContext;
 use alloc::sync::{Arc, Weak};
-use alloc::vec::Vec;
 use core::cell::RefMut;
-use crate::fs::{File, Stdin, Stdout};
-use crate::task::current_task;
-use crate::task::manager::TASK_MANAGER;
-use crate::timer::get_time_milli;
 
 const BIG_STRIDE: usize = 10000;
 const DEFAULT_PRIORITY: usize = 16;
@@ -22,65 +20,44 @@ const DEFAULT_PRIORITY: usize = 16;
 /// Directly save the contents that will not change during running
 pub struct TaskControlBlock {
     // immutable
-    /// Process identifier
-    pub pid: PidHandle,
-    /// Kernel stack corresponding to PID
+    /// The process this thread belongs to
+    pub process: Weak<ProcessControlBlock>,
+    /// Kernel stack of the thread
     pub kernel_stack: KernelStack,
     // mutable
     inner: UPSafeCell<TaskControlBlockInner>,
 }
 
-/// Structure containing more process content
+/// Structure containing more thread content
 ///
 /// Store the contents that will change during operation
 /// and are wrapped by UPSafeCell to provide mutual exclusion
 pub struct TaskControlBlockInner {
+    /// Tid, user stack and trap context, released when the thread exits
+    pub res: Option<TaskUserRes>,
     /// The physical page number of the frame where the trap context is placed
     pub trap_cx_ppn: PhysPageNum,
-    /// Application data can only appear in areas
-    /// where the application address space is lower than base_size
-    pub base_size: usize,
     /// Save task context
     pub task_cx: TaskContext,
-    /// Maintain the execution status of the current process
+    /// Maintain the execution status of the current thread
     pub task_status: TaskStatus,
-    /// Application address space
-    pub memory_set: MemorySet,
-    /// Parent process of the current process.
-    /// Weak will not affect the reference count of the parent
-    pub parent: Option<Weak<TaskControlBlock>>,
-    /// A vector containing TCBs of all child processes of the current process
-    pub children: Vec<Arc<TaskControlBlock>>,
-    /// It is set when active exit or execution error occurs
-    pub exit_code: i32,
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// It is set when the thread exits
+    pub exit_code: Option<i32>,
 
-    pub syscall_times: [u32; MAX_SYSCALL_NUM],
-    pub first_start_time: usize,
     pub priority: usize,
     pub pass: usize,
 
+    /// The signal whose user handler is running on this thread
+    pub handling_sig: Option<usize>,
+    /// Trap context of the code interrupted by the running signal handler
+    pub trap_ctx_backup: Option<TrapContext>,
 }
 
 /// Simple access to its internal fields
 impl TaskControlBlockInner {
-    /*
-    pub fn get_task_cx_ptr2(&self) -> *const usize {
-        &self.task_cx_ptr as *const usize
-    }
-    */
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
-    }
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
-    }
     pub fn add_pass(&mut self) {
         let mut stride = BIG_STRIDE / self.priority;
         if stride < 1 {
@@ -88,168 +65,55 @@ impl TaskControlBlockInner {
         }
         self.pass += stride;
     }
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len())
-            .find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
-        }
-    }
 }
 
 impl TaskControlBlock {
-    /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
-        self.inner.exclusive_access()
-    }
-
-    /// Create a new process
-    ///
-    /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8], parent: Option<&Arc<TaskControlBlock>>) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
+    /// Create a thread of `process`, `alloc_user_res` is false when its user stack
+    /// and trap context are already in the address space.
+    /// ENOMEM if there are no frames for its stacks or trap context
+    pub fn new(process: Arc<ProcessControlBlock>, alloc_user_res: bool) -> SysResult<Self> {
+        let res = TaskUserRes::new(Arc::clone(&process), alloc_user_res)?;
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kernel_stack = kstack_alloc()?;
         let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
-        let task_control_block = Self {
-            pid: pid_handle,
+        Ok(Self {
+            process: Arc::downgrade(&process),
             kernel_stack,
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
+                    res: Some(res),
                     trap_cx_ppn,
-                    base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: parent.map(|p| Arc::downgrade(p)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    first_start_time: get_time_milli(),
+                    exit_code: None,
                     priority: DEFAULT_PRIORITY,
                     pass: 0,
-                    fd_table: alloc::vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
+                    handling_sig: None,
+                    trap_ctx_backup: None,
                 })
             },
-        };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
+        })
     }
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
 
-        // **** access inner exclusively
-        let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize trap_cx
-        let trap_cx = inner.get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        // **** release inner automatically
-    }
-    /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
-        // ---- access parent PCB exclusively
-        let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        // clone all fds from parent to child
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    first_start_time: get_time_milli(),
-                    priority: DEFAULT_PRIORITY,
-                    pass: 0,
-                    fd_table:new_fd_table,
-                })
-            },
-        });
-        // add child
-        parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
-        // **** access children PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // return
-        task_control_block
-        // ---- release parent PCB automatically
-        // **** release children PCB automatically
+    /// Get the mutex to get the RefMut TaskControlBlockInner
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
     }
-    pub fn getpid(&self) -> usize {
-        self.pid.0
+
+    pub fn get_user_token(&self) -> usize {
+        let process = self.process.upgrade().unwrap();
+        let inner = process.inner_exclusive_access();
+        inner.memory_set.token()
     }
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Blocked, Zombie
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
+    /// Waiting in a [`super::WaitQueue`], out of the ready queue
+    Blocked,
     Zombie,
 }
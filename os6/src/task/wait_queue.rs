@@ -0,0 +1,52 @@
+//! Implementation of [`WaitQueue`]
+//!
+//! Threads waiting for an event are kept out of the ready queue, the owner
+//! of the event wakes them up when it happens.
+
+use super::{current_task, wakeup_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// A FIFO queue of blocked threads
+pub struct WaitQueue {
+    tasks: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            tasks: VecDeque::new(),
+        }
+    }
+    /// Put the current thread at the end of the queue.
+    ///
+    /// The caller has to release the object owning the queue and
+    /// call [`super::block_current_and_run_next()`] afterwards
+    pub fn add_current(&mut self) {
+        self.tasks.push_back(current_task().unwrap());
+    }
+    /// Wake up the first thread which is still alive,
+    /// returns false if there is none
+    pub fn wake_one(&mut self) -> bool {
+        while let Some(task) = self.tasks.pop_front() {
+            // the threads of an exited process are dropped
+            if wakeup_task(task) {
+                return true;
+            }
+        }
+        false
+    }
+    /// Wake up all the threads in the queue
+    pub fn wake_all(&mut self) {
+        while self.wake_one() {}
+    }
+    /// Take the current thread out of the queue when it has been woken up by
+    /// a fatal signal instead of the owner of the queue
+    pub fn remove_current(&mut self) {
+        let task = current_task().unwrap();
+        self.tasks.retain(|waiting| !Arc::ptr_eq(waiting, &task));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
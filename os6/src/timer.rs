@@ -1,12 +1,19 @@
 //! RISC-V timer-related functionality
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, RTC_BASE};
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MICRO_PER_SEC: usize = 1_000_000;
 const MILLI_PER_SEC: usize = 1_000;
+const NANO_PER_SEC: u64 = 1_000_000_000;
 /// read the `mtime` register
 pub fn get_time() -> usize {
     time::read()
@@ -25,3 +32,73 @@ pub fn set_next_trigger() {
 pub fn get_time_milli() -> usize {
     time::read() / (CLOCK_FREQ / MILLI_PER_SEC)
 }
+
+/// get current time in seconds since the Unix epoch from the RTC, the clock of the filesystem
+pub fn get_time_sec() -> u32 {
+    // reading TIME_LOW latches TIME_HIGH, so it has to be read first
+    let (low, high) = unsafe {
+        let low = (RTC_BASE as *const u32).read_volatile();
+        let high = ((RTC_BASE + 4) as *const u32).read_volatile();
+        (low, high)
+    };
+    ((((high as u64) << 32) | low as u64) / NANO_PER_SEC) as u32
+}
+
+/// A thread sleeping until `expire_ms`
+pub struct TimerCondVar {
+    pub expire_ms: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The earliest deadline is the greatest, so that it is on top of the max-heap
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_ms.cmp(&self.expire_ms)
+    }
+}
+
+lazy_static! {
+    /// Sleeping threads ordered by deadline
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+}
+
+/// Wake up `task` when the time reaches `expire_ms`, it has to block itself after
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    TIMERS.exclusive_access().push(TimerCondVar { expire_ms, task });
+}
+
+/// Cancel the timers of `task`, which has been woken up by a fatal signal
+pub fn remove_timer(task: &Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.exclusive_access();
+    let mut pending = core::mem::take(&mut *timers).into_vec();
+    pending.retain(|timer| !Arc::ptr_eq(&timer.task, task));
+    *timers = BinaryHeap::from(pending);
+}
+
+/// Wake up the threads whose deadlines have passed.
+///
+/// Called on timer interrupts and when there is no task to run
+pub fn check_timer() {
+    let current_ms = get_time_milli();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms > current_ms {
+            break;
+        }
+        // the thread of an exited process is dropped
+        wakeup_task(timers.pop().unwrap().task);
+    }
+}
@@ -14,12 +14,15 @@
 
 mod context;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::TRAMPOLINE;
+use crate::fs::stdin_poll;
+use crate::mm::VirtAddr;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    current_process, current_raise_fault, current_trap_cx, current_trap_cx_user_va,
+    current_user_token, handle_signals, suspend_current_and_run_next, SignalFlags,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
@@ -61,11 +64,21 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        Trap::Exception(Exception::StorePageFault) if handle_page_fault(stval, true) => {
+            // the page is now backed by a private writable frame, retry the store
+        }
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) if handle_page_fault(stval, false) => {
+            // the page is now backed by a frame, retry the access
+        }
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
@@ -73,21 +86,21 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
             println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, raise SIGSEGV.",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
             );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            current_raise_fault(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            println!("[kernel] IllegalInstruction in application, raise SIGILL.");
+            current_raise_fault(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
+            check_timer();
+            stdin_poll();
             suspend_current_and_run_next();
         }
         _ => {
@@ -101,10 +114,21 @@ pub fn trap_handler() -> ! {
     trap_return();
 }
 
+/// Resolve a page fault on a lazily allocated or copy-on-write page of the current process,
+/// returns false if the fault is a real one
+fn handle_page_fault(stval: usize, is_store: bool) -> bool {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let vpn = VirtAddr::from(stval).floor();
+    inner.memory_set.handle_lazy_fault(vpn) || (is_store && inner.memory_set.handle_cow_fault(vpn))
+}
+
 #[no_mangle]
+/// Deliver pending signals and go back to user mode
 pub fn trap_return() -> ! {
+    handle_signals();
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    let trap_cx_ptr = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
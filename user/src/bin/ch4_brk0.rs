@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{brk, exit, fork, mmap, munmap, sbrk, waitpid};
+
+/*
+理想结果：堆可以通过 brk/sbrk 伸缩，munmap 不能作用于堆，输出 Test 04_9 brk0 OK!
+*/
+
+const PAGE_SIZE: usize = 4096;
+
+fn page_up(addr: usize) -> usize {
+    (addr + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+}
+
+fn page_down(addr: usize) -> usize {
+    addr / PAGE_SIZE * PAGE_SIZE
+}
+
+/// Whether the store at `addr` kills the process, tried in a child
+fn store_faults(addr: usize) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            (addr as *mut u8).write_volatile(1);
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code != 0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // the heap of the allocator may already lie below the break, stay above it
+    let base = sbrk(0);
+    assert!(base > 0);
+    let base = base as usize;
+
+    // grow by two pages, the new memory is usable
+    assert_eq!(sbrk((PAGE_SIZE * 2) as isize), base as isize);
+    assert_eq!(brk(0), (base + PAGE_SIZE * 2) as isize);
+    for addr in base..base + PAGE_SIZE * 2 {
+        unsafe {
+            (addr as *mut u8).write_volatile(addr as u8);
+        }
+    }
+    for addr in base..base + PAGE_SIZE * 2 {
+        unsafe {
+            assert_eq!((addr as *const u8).read_volatile(), addr as u8);
+        }
+    }
+
+    // shrink by one page, the pages above the break are gone
+    assert_eq!(sbrk(-(PAGE_SIZE as isize)), (base + PAGE_SIZE * 2) as isize);
+    assert_eq!(brk(0), (base + PAGE_SIZE) as isize);
+    assert!(store_faults(page_up(base + PAGE_SIZE)));
+    assert!(!store_faults(base));
+
+    // the heap never reaches below its bottom or into another area
+    assert_eq!(brk(1), (base + PAGE_SIZE) as isize);
+    assert_eq!(sbrk(-(base as isize) - 1), -1);
+    let area = page_up(base + PAGE_SIZE) + PAGE_SIZE * 2;
+    assert_eq!(mmap(area, PAGE_SIZE, 3), 0);
+    assert_eq!(brk(area + 1), (base + PAGE_SIZE) as isize);
+    assert_eq!(brk(area), area as isize);
+    assert_eq!(munmap(area, PAGE_SIZE), 0);
+
+    // only brk unmaps the heap
+    assert_eq!(munmap(page_down(base), PAGE_SIZE), -1);
+    assert!(!store_faults(base));
+    assert_eq!(brk(base), base as isize);
+    println!("Test 04_9 brk0 OK!");
+    0
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, getpid, mail_read, mail_write, try_mail_read, try_mail_write, waitpid, yield_,
+    Errno,
+};
+
+/*
+理想结果：邮箱为空时读取失败，邮箱满时写入失败，向不存在的进程写入失败，输出 Test 04_10 mail0 OK!
+*/
+
+/// Number of messages a mailbox holds
+const MAIL_SLOTS: usize = 16;
+/// Longer messages are truncated
+const MAIL_SIZE: usize = 256;
+
+/// Wait for a message, reading never blocks
+fn wait_mail(buf: &mut [u8]) -> usize {
+    loop {
+        match try_mail_read(buf) {
+            Ok(len) => return len,
+            Err(Errno::EAGAIN) => yield_(),
+            Err(errno) => panic!("mail_read failed with {:?}", errno),
+        };
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid() as usize;
+    let mut buf = [0u8; MAIL_SIZE + 44];
+
+    // nothing to read in an empty mailbox
+    assert_eq!(try_mail_read(&mut buf), Err(Errno::EAGAIN));
+    assert_eq!(try_mail_read(&mut []), Err(Errno::EAGAIN));
+
+    // an empty buffer only checks that there is a message, a short one truncates it
+    assert_eq!(mail_write(pid, b"hello"), 5);
+    assert_eq!(try_mail_read(&mut []), Ok(0));
+    assert_eq!(mail_read(&mut buf[..3]), 3);
+    assert_eq!(&buf[..3], b"hel");
+    assert_eq!(try_mail_read(&mut buf), Err(Errno::EAGAIN));
+    let long = [b'x'; MAIL_SIZE + 44];
+    assert_eq!(mail_write(pid, &long), MAIL_SIZE as isize);
+    assert_eq!(mail_read(&mut buf), MAIL_SIZE as isize);
+
+    // a full mailbox refuses more, the messages come out in order
+    for i in 0..MAIL_SLOTS {
+        assert_eq!(mail_write(pid, &[i as u8]), 1);
+    }
+    assert_eq!(try_mail_write(pid, b"full"), Err(Errno::ENOSPC));
+    assert_eq!(try_mail_write(pid, &[]), Err(Errno::ENOSPC));
+    for i in 0..MAIL_SLOTS {
+        assert_eq!(mail_read(&mut buf), 1);
+        assert_eq!(buf[0], i as u8);
+    }
+    assert_eq!(try_mail_read(&mut buf), Err(Errno::EAGAIN));
+    assert_eq!(try_mail_write(pid, &[]), Ok(0));
+
+    // between processes, no mailbox once the process is gone
+    let child = fork();
+    if child == 0 {
+        let len = wait_mail(&mut buf);
+        assert_eq!(&buf[..len], b"ping");
+        assert_eq!(mail_write(pid, b"pong"), 4);
+        exit(0);
+    }
+    assert_eq!(mail_write(child as usize, b"ping"), 4);
+    let len = wait_mail(&mut buf);
+    assert_eq!(&buf[..len], b"pong");
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, 0);
+    assert_eq!(try_mail_write(child as usize, b"ping"), Err(Errno::ESRCH));
+    println!("Test 04_10 mail0 OK!");
+    0
+}
@@ -0,0 +1,101 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, fstat, mmap_file, munmap, open, read, unlink, waitpid, write, MapFlags,
+    OpenFlags, Stat,
+};
+
+/*
+理想结果：共享映射的修改写回文件，私有映射的修改不写回，输出 Test 04_8 mmap4 OK!
+*/
+
+const CONTENT: &[u8] = b"0123456789";
+
+/// Read the whole file at `path` into `buf`, returns the length
+fn read_file(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let len = read(fd as usize, buf) as usize;
+    close(fd as usize);
+    len
+}
+
+fn load(addr: usize) -> u8 {
+    unsafe { (addr as *const u8).read_volatile() }
+}
+
+fn store(addr: usize, byte: u8) {
+    unsafe { (addr as *mut u8).write_volatile(byte) }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    let prot: usize = 3;
+    let fname = "mmap4\0";
+    let fd = open(fname, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, CONTENT), CONTENT.len() as isize);
+    let mut buf = [0u8; 32];
+
+    // the page holds the file, zeros after its end
+    assert_eq!(mmap_file(start, len, prot, MapFlags::SHARED, fd, 0), 0);
+    for (i, &byte) in CONTENT.iter().enumerate() {
+        assert_eq!(load(start + i), byte);
+    }
+    assert!((start + CONTENT.len()..start + len).all(|addr| load(addr) == 0));
+    // stores of a shared mapping reach the file when it is unmapped, without extending it
+    store(start, b'A');
+    store(start + CONTENT.len() + 1, b'X');
+    assert_eq!(munmap(start, len), 0);
+    assert_eq!(read_file(fname, &mut buf), CONTENT.len());
+    assert_eq!(&buf[..CONTENT.len()], b"A123456789");
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    assert_eq!(stat.size, CONTENT.len() as u64);
+
+    // stores of a private mapping never do
+    assert_eq!(mmap_file(start, len, prot, MapFlags::PRIVATE, fd, 0), 0);
+    assert_eq!(load(start), b'A');
+    store(start + 1, b'B');
+    assert_eq!(munmap(start, len), 0);
+    assert_eq!(read_file(fname, &mut buf), CONTENT.len());
+    assert_eq!(&buf[..CONTENT.len()], b"A123456789");
+
+    // a shared mapping is shared with the child after fork
+    assert_eq!(mmap_file(start, len, prot, MapFlags::SHARED, fd, 0), 0);
+    assert_eq!(load(start + 2), b'2');
+    let pid = fork();
+    if pid == 0 {
+        store(start + 2, b'C');
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(load(start + 2), b'C');
+    assert_eq!(munmap(start, len), 0);
+    assert_eq!(read_file(fname, &mut buf), CONTENT.len());
+    assert_eq!(&buf[..CONTENT.len()], b"A1C3456789");
+    close(fd);
+
+    // the offset is page aligned, a shared writable mapping needs a writable file
+    let fd = open(fname, OpenFlags::RDONLY) as usize;
+    assert_eq!(mmap_file(start, len, prot, MapFlags::SHARED, fd, 1), -1);
+    assert_eq!(mmap_file(start, len, prot, MapFlags::SHARED, fd, 0), -1);
+    assert_eq!(mmap_file(start, len, 1, MapFlags::SHARED, fd, 0), 0);
+    assert_eq!(munmap(start, len), 0);
+    assert_eq!(mmap_file(start, len, prot, MapFlags::PRIVATE, fd, 0), 0);
+    assert_eq!(munmap(start, len), 0);
+    close(fd);
+    assert_eq!(mmap_file(start, len, prot, MapFlags::SHARED, fd, 0), -1);
+    unlink(fname);
+    println!("Test 04_8 mmap4 OK!");
+    0
+}
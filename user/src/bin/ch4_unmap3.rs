@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, mmap, mprotect, munmap, waitpid};
+
+/*
+理想结果：munmap 与 mprotect 可以只作用于映射区域的一部分，输出 Test 04_7 unmap3 OK!
+*/
+
+/// Whether the store at `addr` kills the process, tried in a child
+fn store_faults(addr: usize) -> bool {
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            (addr as *mut u8).write_volatile(1);
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code != 0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    let prot: usize = 3;
+    assert_eq!(0, mmap(start, len * 4, prot));
+    for i in 0..4 {
+        unsafe {
+            ((start + i * len) as *mut u8).write_volatile(i as u8);
+        }
+    }
+
+    // a hole in the middle, the pages around it stay mapped
+    assert_eq!(munmap(start + len, len), 0);
+    assert!(store_faults(start + len));
+    assert!(!store_faults(start + len * 2));
+    assert_eq!(munmap(start, len * 4), -1);
+    unsafe {
+        assert_eq!((start as *const u8).read_volatile(), 0);
+        assert_eq!(((start + len * 3) as *const u8).read_volatile(), 3);
+    }
+    // and the hole can be mapped again
+    assert_eq!(mmap(start + len, len, prot), 0);
+    assert!(!store_faults(start + len));
+
+    // a read-only page in the middle of a writable area
+    assert_eq!(mprotect(start + len * 2, len, 1), 0);
+    unsafe {
+        assert_eq!(((start + len * 2) as *const u8).read_volatile(), 2);
+    }
+    assert!(store_faults(start + len * 2));
+    assert!(!store_faults(start + len * 3));
+    assert_eq!(mprotect(start + len * 2, len, prot), 0);
+    assert!(!store_faults(start + len * 2));
+    assert_eq!(mprotect(start + len * 3, len * 2, 1), -1);
+    assert_eq!(mprotect(start, len, 0), -1);
+    assert_eq!(mprotect(start, len, 8), -1);
+
+    // the end of the area alone
+    assert_eq!(munmap(start + len * 3, len), 0);
+    assert!(store_faults(start + len * 3));
+    assert_eq!(munmap(start, len * 3), 0);
+    println!("Test 04_7 unmap3 OK!");
+    0
+}
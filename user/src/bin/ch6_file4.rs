@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    chmod, close, fstat, open, read, try_chmod, try_utimensat, unlink, utimensat, write, Errno,
+    OpenFlags, Stat, StatMode, TimeSpec, UTIME_OMIT,
+};
+
+/// 测试 chmod/utimensat 以及 Stat 中的权限与时间，输出 Test chmod/utimensat OK! 就算正确。
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let test_str = "Hello, world!";
+    let fname = "fname4\0";
+    let fd = open(fname, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    let stat = Stat::new();
+    fstat(fd, &stat);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.perm, 0o644);
+    assert_eq!(stat.size, test_str.len() as u64);
+    assert!(stat.mtime > 0);
+    close(fd);
+
+    // chmod keeps the file type and only changes the permission bits
+    assert_eq!(chmod(fname, 0o600), 0);
+    let fd = open(fname, OpenFlags::RDONLY) as usize;
+    fstat(fd, &stat);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.perm, 0o600);
+    assert_eq!(try_chmod("fname4_missing\0", 0o600), Err(Errno::ENOENT));
+
+    // explicit times, then only the modification time
+    let times = [TimeSpec { sec: 1000, nsec: 0 }, TimeSpec { sec: 2000, nsec: 0 }];
+    assert_eq!(utimensat(fname, Some(&times), 0), 0);
+    fstat(fd, &stat);
+    assert_eq!(stat.atime, 1000);
+    assert_eq!(stat.mtime, 2000);
+    let times = [TimeSpec { sec: 0, nsec: UTIME_OMIT }, TimeSpec { sec: 3000, nsec: 0 }];
+    assert_eq!(utimensat(fname, Some(&times), 0), 0);
+    fstat(fd, &stat);
+    assert_eq!(stat.atime, 1000);
+    assert_eq!(stat.mtime, 3000);
+    let times = [TimeSpec { sec: 0, nsec: 1_000_000_000 }, TimeSpec { sec: 0, nsec: 0 }];
+    assert_eq!(try_utimensat(fname, Some(&times), 0), Err(Errno::EINVAL));
+
+    // the access time older than the modification time is refreshed by a read
+    let mut buf = [0u8; 100];
+    let read_len = read(fd, &mut buf) as usize;
+    assert_eq!(test_str, core::str::from_utf8(&buf[..read_len]).unwrap());
+    fstat(fd, &stat);
+    assert!(stat.atime > 3000);
+    assert_eq!(stat.mtime, 3000);
+
+    // no times means now for both
+    assert_eq!(utimensat(fname, None, 0), 0);
+    fstat(fd, &stat);
+    assert!(stat.mtime > 3000);
+    assert_eq!(stat.atime, stat.mtime);
+    close(fd);
+    unlink(fname);
+    println!("Test chmod/utimensat OK!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, mkdir, open, read, rename, try_open, try_rename, try_renameat2, write, Errno,
+    OpenFlags, RENAME_NOREPLACE,
+};
+
+/// 测试 rename/renameat2，输出 Test rename OK! 就算正确。
+
+fn write_file(path: &str, content: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, content.as_bytes()), content.len() as isize);
+    close(fd as usize);
+}
+
+fn read_file<'a>(path: &str, buf: &'a mut [u8]) -> &'a str {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let len = read(fd as usize, buf) as usize;
+    close(fd as usize);
+    core::str::from_utf8(&buf[..len]).unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 32];
+
+    // an existing file is replaced in one step
+    write_file("rename_a\0", "aaa");
+    write_file("rename_b\0", "bbb");
+    assert_eq!(rename("rename_a\0", "rename_b\0"), 0);
+    assert_eq!(read_file("rename_b\0", &mut buf), "aaa");
+    assert_eq!(try_open("rename_a\0", OpenFlags::RDONLY), Err(Errno::ENOENT));
+
+    // but not with RENAME_NOREPLACE
+    write_file("rename_c\0", "ccc");
+    assert_eq!(
+        try_renameat2("rename_c\0", "rename_b\0", RENAME_NOREPLACE),
+        Err(Errno::EEXIST)
+    );
+    assert_eq!(read_file("rename_b\0", &mut buf), "aaa");
+    assert_eq!(read_file("rename_c\0", &mut buf), "ccc");
+    assert_eq!(try_renameat2("rename_c\0", "rename_a\0", RENAME_NOREPLACE), Ok(0));
+    assert_eq!(read_file("rename_a\0", &mut buf), "ccc");
+
+    // a directory cannot be moved into itself
+    assert_eq!(mkdir("rename_d\0"), 0);
+    assert_eq!(mkdir("rename_d/sub\0"), 0);
+    assert_eq!(try_rename("rename_d\0", "rename_d/sub/d\0"), Err(Errno::EINVAL));
+    assert_eq!(try_rename("rename_d\0", "rename_d/d\0"), Err(Errno::EINVAL));
+
+    // a directory only replaces an empty directory
+    assert_eq!(mkdir("rename_e\0"), 0);
+    write_file("rename_e/file\0", "eee");
+    assert_eq!(try_rename("rename_d/sub\0", "rename_e\0"), Err(Errno::ENOTEMPTY));
+    assert_eq!(try_rename("rename_d/sub\0", "rename_a\0"), Err(Errno::ENOTDIR));
+    assert_eq!(try_rename("rename_a\0", "rename_e\0"), Err(Errno::EISDIR));
+    assert_eq!(mkdir("rename_f\0"), 0);
+    assert_eq!(rename("rename_d/sub\0", "rename_f\0"), 0);
+    assert_eq!(try_open("rename_d/sub\0", OpenFlags::RDONLY), Err(Errno::ENOENT));
+
+    // moving a file between directories keeps its content
+    assert_eq!(rename("rename_e/file\0", "rename_f/file\0"), 0);
+    assert_eq!(read_file("rename_f/file\0", &mut buf), "eee");
+    println!("Test rename OK!");
+    0
+}
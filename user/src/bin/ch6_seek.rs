@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, lseek, open, pipe, pread, pwrite, read, try_lseek, try_pread, try_pwrite, try_write,
+    unlink, write, Errno, OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// 测试 lseek/pread/pwrite，输出 Test seek OK! 就算正确。
+
+/// Largest file of easy-fs: 22 direct, 128 indirect and 128 * 128 double indirect blocks
+const MAX_FILE_SIZE: usize = (22 + 128 + 128 * 128) * 512;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fname = "seek0\0";
+    let fd = open(fname, OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"0123456789"), 10);
+
+    // the three bases of lseek
+    assert_eq!(lseek(fd, 2, SEEK_SET), 2);
+    assert_eq!(lseek(fd, 3, SEEK_CUR), 5);
+    assert_eq!(lseek(fd, -4, SEEK_END), 6);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(fd, &mut buf[..2]), 2);
+    assert_eq!(&buf[..2], b"67");
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 8);
+    assert_eq!(try_lseek(fd, -9, SEEK_CUR), Err(Errno::EINVAL));
+    assert_eq!(try_lseek(fd, 0, 3), Err(Errno::EINVAL));
+    assert_eq!(try_lseek(fd, isize::MAX, SEEK_END), Err(Errno::EINVAL));
+
+    // writing past the end leaves a hole of zeros
+    assert_eq!(lseek(fd, 12, SEEK_SET), 12);
+    assert_eq!(write(fd, b"ab"), 2);
+    assert_eq!(lseek(fd, 0, SEEK_END), 14);
+    assert_eq!(pread(fd, &mut buf[..4], 10), 4);
+    assert_eq!(&buf[..4], b"\0\0ab");
+
+    // pread and pwrite do not move the offset
+    assert_eq!(lseek(fd, 1, SEEK_SET), 1);
+    assert_eq!(pwrite(fd, b"xy", 4), 2);
+    assert_eq!(pread(fd, &mut buf[..6], 2), 6);
+    assert_eq!(&buf[..6], b"23xy67");
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 1);
+    assert_eq!(pread(fd, &mut buf, 14), 0);
+
+    // no file grows beyond the largest size
+    assert_eq!(try_pwrite(fd, b"z", MAX_FILE_SIZE), Err(Errno::EFBIG));
+    assert_eq!(lseek(fd, MAX_FILE_SIZE as isize, SEEK_SET), MAX_FILE_SIZE as isize);
+    assert_eq!(try_write(fd, b"z"), Err(Errno::EFBIG));
+    assert_eq!(lseek(fd, 0, SEEK_END), 14);
+    close(fd);
+    unlink(fname);
+
+    // pipes and the console are not seekable
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(try_lseek(pipe_fd[0], 0, SEEK_SET), Err(Errno::ESPIPE));
+    assert_eq!(try_pread(pipe_fd[0], &mut buf, 0), Err(Errno::ESPIPE));
+    assert_eq!(try_pwrite(pipe_fd[1], b"a", 0), Err(Errno::ESPIPE));
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    assert_eq!(try_lseek(0, 0, SEEK_CUR), Err(Errno::ESPIPE));
+    assert_eq!(try_lseek(1, 0, SEEK_CUR), Err(Errno::ESPIPE));
+    println!("Test seek OK!");
+    0
+}
@@ -0,0 +1,98 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{
+    close, fstatat, mkdir, open, read, readlink, symlink, try_fstatat, try_open, try_readlink,
+    try_symlink, unlink, write, Errno, OpenFlags, Stat, StatMode, AT_SYMLINK_NOFOLLOW,
+};
+
+/// 测试 symlink/readlink 以及路径中符号链接的解析，输出 Test symlink OK! 就算正确。
+
+/// Number of links followed by one lookup before ELOOP
+const MAX_SYMLINKS: usize = 8;
+
+fn write_file(path: &str, content: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    assert_eq!(write(fd as usize, content.as_bytes()), content.len() as isize);
+    close(fd as usize);
+}
+
+fn read_file<'a>(path: &str, buf: &'a mut [u8]) -> &'a str {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let len = read(fd as usize, buf) as usize;
+    close(fd as usize);
+    core::str::from_utf8(&buf[..len]).unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 32];
+    write_file("symlink_f\0", "hello");
+
+    // the target is stored as it is, without a trailing '\0'
+    assert_eq!(symlink("symlink_f\0", "symlink_l\0"), 0);
+    assert_eq!(try_symlink("symlink_f\0", "symlink_l\0"), Err(Errno::EEXIST));
+    assert_eq!(readlink("symlink_l\0", &mut buf), 9);
+    assert_eq!(&buf[..9], b"symlink_f");
+    assert_eq!(readlink("symlink_l\0", &mut buf[..4]), 4);
+    assert_eq!(&buf[..4], b"syml");
+    assert_eq!(try_readlink("symlink_f\0", &mut buf), Err(Errno::EINVAL));
+
+    // the link is followed unless AT_SYMLINK_NOFOLLOW is given
+    assert_eq!(read_file("symlink_l\0", &mut buf), "hello");
+    let stat = Stat::new();
+    assert_eq!(fstatat("symlink_l\0", &stat, 0), 0);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.size, 5);
+    assert_eq!(fstatat("symlink_l\0", &stat, AT_SYMLINK_NOFOLLOW), 0);
+    assert_eq!(stat.mode, StatMode::LNK);
+    assert_eq!(stat.size, 9);
+
+    // a dangling link exists by itself only
+    assert_eq!(symlink("symlink_none\0", "symlink_dangling\0"), 0);
+    assert_eq!(try_open("symlink_dangling\0", OpenFlags::RDONLY), Err(Errno::ENOENT));
+    assert_eq!(try_fstatat("symlink_dangling\0", &stat, 0), Err(Errno::ENOENT));
+    assert_eq!(fstatat("symlink_dangling\0", &stat, AT_SYMLINK_NOFOLLOW), 0);
+    assert_eq!(stat.mode, StatMode::LNK);
+
+    // links in the middle of a path, a relative target starts from the directory of the link
+    assert_eq!(mkdir("symlink_d\0"), 0);
+    write_file("symlink_d/f\0", "world");
+    assert_eq!(symlink("symlink_d\0", "symlink_dl\0"), 0);
+    assert_eq!(read_file("symlink_dl/f\0", &mut buf), "world");
+    assert_eq!(symlink("f\0", "symlink_d/lf\0"), 0);
+    assert_eq!(read_file("symlink_dl/lf\0", &mut buf), "world");
+
+    // a chain of links is followed up to MAX_SYMLINKS
+    assert_eq!(symlink("symlink_f\0", "symlink_c1\0"), 0);
+    for i in 2..=MAX_SYMLINKS + 1 {
+        let target = format!("symlink_c{}\0", i - 1);
+        let link = format!("symlink_c{}\0", i);
+        assert_eq!(symlink(&target, &link), 0);
+    }
+    let last = format!("symlink_c{}\0", MAX_SYMLINKS);
+    assert_eq!(read_file(&last, &mut buf), "hello");
+    let too_long = format!("symlink_c{}\0", MAX_SYMLINKS + 1);
+    assert_eq!(try_open(&too_long, OpenFlags::RDONLY), Err(Errno::ELOOP));
+    assert_eq!(fstatat(&too_long, &stat, AT_SYMLINK_NOFOLLOW), 0);
+
+    // a loop never resolves
+    assert_eq!(symlink("symlink_y\0", "symlink_x\0"), 0);
+    assert_eq!(symlink("symlink_x\0", "symlink_y\0"), 0);
+    assert_eq!(try_open("symlink_x\0", OpenFlags::RDONLY), Err(Errno::ELOOP));
+    assert_eq!(try_open("symlink_x/f\0", OpenFlags::RDONLY), Err(Errno::ELOOP));
+
+    // unlink removes the link, not its target
+    assert_eq!(unlink("symlink_l\0"), 0);
+    assert_eq!(try_readlink("symlink_l\0", &mut buf), Err(Errno::ENOENT));
+    assert_eq!(read_file("symlink_f\0", &mut buf), "hello");
+    println!("Test symlink OK!");
+    0
+}
@@ -20,6 +20,10 @@ static TESTS: &[&str] = &[
     "ch4_mmap3\0",
     "ch4_unmap\0",
     "ch4_unmap2\0",
+    "ch4_unmap3\0",
+    "ch4_mmap4\0",
+    "ch4_brk0\0",
+    "ch4_mail0\0",
     "ch5b_forktest2\0",
     "ch5_spawn0\0",
     "ch5_spawn1\0",
@@ -27,6 +31,10 @@ static TESTS: &[&str] = &[
     "ch6_file1\0",
     "ch6_file2\0",
     "ch6_file3\0",
+    "ch6_file4\0",
+    "ch6_rename\0",
+    "ch6_seek\0",
+    "ch6_symlink\0",
 ];
 
 use user_lib::{spawn, waitpid};
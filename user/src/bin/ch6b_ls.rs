@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{try_close, try_getdents, try_open, DirEntry, OpenFlags, DT_DIR, DT_LNK};
+
+/// List the directory in argv[1], or the current directory
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    // the strings in argv are followed by '\0'
+    let path = if argc > 1 { argv[1] } else { ".\0" };
+    let fd = match try_open(path, OpenFlags::RDONLY | OpenFlags::DIRECTORY) {
+        Ok(fd) => fd,
+        Err(err) => {
+            println!("ls: cannot open {}: {:?}", path.trim_end_matches('\0'), err);
+            return -1;
+        }
+    };
+    let mut buf = [0u8; 512];
+    loop {
+        let size = match try_getdents(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(size) => size,
+            Err(err) => {
+                println!("ls: cannot read {}: {:?}", path.trim_end_matches('\0'), err);
+                let _ = try_close(fd);
+                return -1;
+            }
+        };
+        for entry in DirEntry::parse(&buf[..size]) {
+            match entry.d_type {
+                DT_DIR => println!("{}/", entry.name),
+                DT_LNK => println!("{}@", entry.name),
+                _ => println!("{}", entry.name),
+            }
+        }
+    }
+    let _ = try_close(fd);
+    0
+}
@@ -12,8 +12,152 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, waitpid};
+use user_lib::{chdir, close, dup2, exec, flush, fork, open, pipe, waitpid, OpenFlags};
+
+/// One command of a pipeline with its redirections
+struct ProcessArguments {
+    input: String,
+    output: String,
+    args_copy: Vec<String>,
+    args_addr: Vec<*const u8>,
+}
+
+impl ProcessArguments {
+    pub fn new(command: &str) -> Self {
+        let mut args_copy: Vec<String> = command
+            .split(' ')
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| {
+                let mut string = String::from(arg);
+                string.push('\0');
+                string
+            })
+            .collect();
+
+        // redirect input
+        let mut input = String::new();
+        if let Some(idx) = args_copy.iter().position(|arg| arg.as_str() == "<\0") {
+            if idx + 1 < args_copy.len() {
+                input = args_copy[idx + 1].clone();
+                args_copy.drain(idx..=idx + 1);
+            }
+        }
+
+        // redirect output
+        let mut output = String::new();
+        if let Some(idx) = args_copy.iter().position(|arg| arg.as_str() == ">\0") {
+            if idx + 1 < args_copy.len() {
+                output = args_copy[idx + 1].clone();
+                args_copy.drain(idx..=idx + 1);
+            }
+        }
+
+        let mut args_addr: Vec<*const u8> = args_copy.iter().map(|arg| arg.as_ptr()).collect();
+        args_addr.push(core::ptr::null::<u8>());
+
+        Self {
+            input,
+            output,
+            args_copy,
+            args_addr,
+        }
+    }
+    /// Whether the command is well formed at position `i` of a pipeline of `count` commands:
+    /// only the first one may read a file and only the last one may write one
+    fn is_valid(&self, i: usize, count: usize) -> bool {
+        !self.args_copy.is_empty()
+            && (i == 0 || self.input.is_empty())
+            && (i == count - 1 || self.output.is_empty())
+    }
+}
+
+/// Run the child at position `i` of the pipeline, returns only on failure
+fn run_child(process_argument: &ProcessArguments, i: usize, pipes_fd: &[[usize; 2]]) -> i32 {
+    let input = &process_argument.input;
+    let output = &process_argument.output;
+    // redirect input, the original fd is closed by exec
+    if !input.is_empty() {
+        let input_fd = open(input.as_str(), OpenFlags::RDONLY | OpenFlags::CLOEXEC);
+        if input_fd == -1 {
+            println!("Error when opening file {}", input.trim_end_matches('\0'));
+            return -4;
+        }
+        assert_eq!(dup2(input_fd as usize, 0), 0);
+    }
+    // redirect output
+    if !output.is_empty() {
+        let output_fd = open(
+            output.as_str(),
+            OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::CLOEXEC,
+        );
+        if output_fd == -1 {
+            println!("Error when opening file {}", output.trim_end_matches('\0'));
+            return -4;
+        }
+        assert_eq!(dup2(output_fd as usize, 1), 1);
+    }
+    // receive input from the previous process
+    if i > 0 {
+        assert_eq!(dup2(pipes_fd[i - 1][0], 0), 0);
+    }
+    // send output to the next process
+    if i < pipes_fd.len() {
+        assert_eq!(dup2(pipes_fd[i][1], 1), 1);
+    }
+    // close all pipe ends inherited from the shell
+    for pipe_fd in pipes_fd.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    let args_copy = &process_argument.args_copy;
+    if exec(args_copy[0].as_str(), process_argument.args_addr.as_slice()) == -1 {
+        println!("Error when executing!");
+        return -4;
+    }
+    unreachable!();
+}
+
+/// Run a pipeline of commands separated by `|`, each may redirect with `<` and `>`
+fn run_line(line: &str) {
+    let process_arguments_list: Vec<ProcessArguments> =
+        line.split('|').map(ProcessArguments::new).collect();
+    let count = process_arguments_list.len();
+    if !process_arguments_list
+        .iter()
+        .enumerate()
+        .all(|(i, process_args)| process_args.is_valid(i, count))
+    {
+        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
+        return;
+    }
+    // create pipes between neighbouring commands
+    let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+    for _ in 0..count - 1 {
+        let mut pipe_fd = [0usize; 2];
+        pipe(&mut pipe_fd);
+        pipes_fd.push(pipe_fd);
+    }
+    let mut children: Vec<isize> = Vec::new();
+    for (i, process_argument) in process_arguments_list.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            user_lib::exit(run_child(process_argument, i, &pipes_fd));
+        }
+        children.push(pid);
+    }
+    for pipe_fd in pipes_fd.iter() {
+        close(pipe_fd[0]);
+        close(pipe_fd[1]);
+    }
+    for pid in children.into_iter() {
+        let mut exit_code: i32 = 0;
+        let exit_pid = waitpid(pid as usize, &mut exit_code);
+        assert_eq!(pid, exit_pid);
+        println!("Shell: Process {} exited with code {}", pid, exit_code);
+    }
+}
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -26,24 +170,16 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 print!("\n");
-                if !line.is_empty() {
-                    line.push('\0');
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str(), &[0 as *const u8]) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                if let Some(dir) = line.strip_prefix("cd ") {
+                    let mut dir = String::from(dir.trim());
+                    dir.push('\0');
+                    if chdir(dir.as_str()) != 0 {
+                        println!("cd: {}: No such directory", dir.trim_end_matches('\0'));
                     }
-                    line.clear();
+                } else if !line.trim().is_empty() {
+                    run_line(line.as_str());
                 }
+                line.clear();
                 print!(">> ");
                 flush();
             }
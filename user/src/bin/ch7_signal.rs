@@ -0,0 +1,155 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, getpid, kill, sigaction, sigprocmask, sigreturn, waitpid, yield_, SignalAction,
+    SignalFlags, SIGILL, SIGKILL, SIGSEGV, SIGUSR1, SIGUSR2,
+};
+
+/// 测试 kill/sigaction/sigprocmask/sigreturn 以及异常产生的 SIGSEGV/SIGILL，输出 Test signal OK! 就算正确。
+
+static USR1_COUNT: AtomicUsize = AtomicUsize::new(0);
+static USR2_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Value of USR2_COUNT seen by the SIGUSR1 handler after raising SIGUSR2
+static USR2_IN_USR1: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+extern "C" fn usr1_handler(signum: usize) {
+    assert_eq!(signum, SIGUSR1);
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+    sigreturn();
+}
+
+/// SIGUSR2 is blocked while this handler runs
+extern "C" fn usr1_raise_usr2_handler(_signum: usize) {
+    USR1_COUNT.fetch_add(1, Ordering::SeqCst);
+    kill(getpid() as usize, SIGUSR2);
+    USR2_IN_USR1.store(USR2_COUNT.load(Ordering::SeqCst), Ordering::SeqCst);
+    sigreturn();
+}
+
+extern "C" fn usr2_handler(_signum: usize) {
+    USR2_COUNT.fetch_add(1, Ordering::SeqCst);
+    sigreturn();
+}
+
+/// The faulting instruction would run again after sigreturn, so leave from the handler
+extern "C" fn fault_handler(signum: usize) {
+    exit(signum as i32 + 100);
+}
+
+fn set_handler(signum: usize, handler: extern "C" fn(usize), mask: SignalFlags) {
+    let action = SignalAction {
+        handler: handler as usize,
+        mask,
+    };
+    assert_eq!(sigaction(signum, Some(&action), None), 0);
+}
+
+/// Run `f` in a child process and return its exit code
+fn exit_code_of(f: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        f();
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn bad_address() {
+    unsafe {
+        #[allow(clippy::zero_ptr)]
+        (0x0 as *mut u8).write_volatile(0);
+    }
+}
+
+fn bad_instruction() {
+    unsafe {
+        core::arch::asm!("sret");
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+
+    // delivered on the way back from kill, a0 of kill is restored by sigreturn
+    set_handler(SIGUSR1, usr1_handler, SignalFlags::empty());
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+
+    // a blocked signal stays pending until it is unblocked
+    let old_mask = sigprocmask(SignalFlags::SIGUSR1);
+    assert_eq!(old_mask, SignalFlags::empty());
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 1);
+    assert_eq!(sigprocmask(SignalFlags::empty()), SignalFlags::SIGUSR1);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 2);
+
+    // the mask of an action blocks signals while its handler runs
+    set_handler(SIGUSR1, usr1_raise_usr2_handler, SignalFlags::SIGUSR2);
+    set_handler(SIGUSR2, usr2_handler, SignalFlags::empty());
+    assert_eq!(kill(pid, SIGUSR1), 0);
+    assert_eq!(USR1_COUNT.load(Ordering::SeqCst), 3);
+    assert_eq!(USR2_IN_USR1.load(Ordering::SeqCst), 0);
+    assert_eq!(USR2_COUNT.load(Ordering::SeqCst), 1);
+
+    // SIGKILL can neither be caught nor blocked
+    set_handler(SIGUSR1, usr1_handler, SignalFlags::empty());
+    let action = SignalAction {
+        handler: usr1_handler as usize,
+        mask: SignalFlags::empty(),
+    };
+    assert_eq!(sigaction(SIGKILL, Some(&action), None), -1);
+    sigprocmask(SignalFlags::SIGKILL);
+    assert_eq!(sigprocmask(SignalFlags::empty()), SignalFlags::empty());
+
+    // faults terminate with the negated signal number without a handler
+    assert_eq!(exit_code_of(bad_address), -(SIGSEGV as i32));
+    assert_eq!(exit_code_of(bad_instruction), -(SIGILL as i32));
+    // and can be caught by one
+    assert_eq!(
+        exit_code_of(|| {
+            set_handler(SIGSEGV, fault_handler, SignalFlags::empty());
+            bad_address();
+        }),
+        SIGSEGV as i32 + 100
+    );
+    assert_eq!(
+        exit_code_of(|| {
+            set_handler(SIGILL, fault_handler, SignalFlags::empty());
+            bad_instruction();
+        }),
+        SIGILL as i32 + 100
+    );
+
+    // the default action of SIGUSR2 terminates, SIGKILL terminates a running process
+    let child = fork();
+    if child == 0 {
+        loop {
+            yield_();
+        }
+    }
+    assert_eq!(kill(child as usize, SIGKILL), 0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(child as usize, &mut exit_code), child);
+    assert_eq!(exit_code, -(SIGKILL as i32));
+    assert_eq!(
+        exit_code_of(|| {
+            // the handlers are inherited, go back to the default action
+            sigaction(SIGUSR2, Some(&SignalAction::default()), None);
+            kill(getpid() as usize, SIGUSR2);
+            loop {
+                yield_();
+            }
+        }),
+        -(SIGUSR2 as i32)
+    );
+    println!("Test signal OK!");
+    0
+}
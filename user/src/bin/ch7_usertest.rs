@@ -5,6 +5,7 @@
 extern crate user_lib;
 
 static TESTS: &[&str] = &[
+    "ch7_signal\0",
 ];
 
 use user_lib::{spawn, waitpid};
@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, pipe, read, sigprocmask, try_fcntl, try_write, waitpid, write,
+    Errno, SignalFlags, F_GETPIPE_SZ, F_SETPIPE_SZ, SIGPIPE,
+};
+
+/// 测试管道容量的设置、多个写者的原子写入以及读端关闭后的 EPIPE/SIGPIPE，输出 pipetest2 passed! 就算正确。
+
+const PAGE_SIZE: usize = 4096;
+/// Writes up to this size are not interleaved
+const PIPE_BUF: usize = PAGE_SIZE;
+/// Below PIPE_BUF and not dividing the capacity, a write often finds only part of its room
+const CHUNK: usize = 1500;
+const WRITERS: usize = 4;
+const CHUNKS_PER_WRITER: usize = 8;
+
+fn pipe_size() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+    assert_eq!(fcntl(read_end, F_GETPIPE_SZ, 0), PIPE_BUF as isize);
+
+    // the size is rounded up to pages and shared by both ends
+    assert_eq!(fcntl(write_end, F_SETPIPE_SZ, 2 * PAGE_SIZE + 1), 3 * PAGE_SIZE as isize);
+    assert_eq!(fcntl(read_end, F_GETPIPE_SZ, 0), 3 * PAGE_SIZE as isize);
+    assert_eq!(try_fcntl(write_end, F_SETPIPE_SZ, 17 * PAGE_SIZE), Err(Errno::EPERM));
+    assert_eq!(try_fcntl(1, F_GETPIPE_SZ, 0), Err(Errno::EBADF));
+
+    // more than the default size fits now, the buffered data blocks shrinking below it
+    let mut buf = [0u8; CHUNK];
+    for i in 0..4 {
+        buf.fill(i as u8);
+        assert_eq!(write(write_end, &buf), CHUNK as isize);
+    }
+    assert_eq!(try_fcntl(write_end, F_SETPIPE_SZ, PAGE_SIZE), Err(Errno::EBUSY));
+    assert_eq!(fcntl(write_end, F_SETPIPE_SZ, 2 * PAGE_SIZE), 2 * PAGE_SIZE as isize);
+    // the data survives resizing in order
+    for i in 0..4 {
+        assert_eq!(read(read_end, &mut buf), CHUNK as isize);
+        assert!(buf.iter().all(|&byte| byte == i as u8));
+    }
+    assert_eq!(fcntl(read_end, F_SETPIPE_SZ, 1), PIPE_BUF as isize);
+    close(read_end);
+    close(write_end);
+}
+
+fn atomic_writes() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let (read_end, write_end) = (pipe_fd[0], pipe_fd[1]);
+    let mut pids = [0isize; WRITERS];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            close(read_end);
+            let buf = [b'a' + i as u8; CHUNK];
+            for _ in 0..CHUNKS_PER_WRITER {
+                assert_eq!(write(write_end, &buf), CHUNK as isize);
+            }
+            exit(0);
+        }
+    }
+    close(write_end);
+    // every chunk read comes from a single writer
+    let mut counts = [0usize; WRITERS];
+    let mut buf = [0u8; CHUNK];
+    for _ in 0..WRITERS * CHUNKS_PER_WRITER {
+        assert_eq!(read(read_end, &mut buf), CHUNK as isize);
+        let writer = (buf[0] - b'a') as usize;
+        assert!(buf.iter().all(|&byte| byte == buf[0]));
+        counts[writer] += 1;
+    }
+    assert!(counts.iter().all(|&count| count == CHUNKS_PER_WRITER));
+    // all writers are gone
+    assert_eq!(read(read_end, &mut buf), 0);
+    close(read_end);
+    for &pid in pids.iter() {
+        let mut exit_code: i32 = 0;
+        assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+}
+
+fn broken_pipe() {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    let pid = fork();
+    if pid == 0 {
+        // the write fails with SIGPIPE pending, which terminates once unblocked
+        sigprocmask(SignalFlags::SIGPIPE);
+        assert_eq!(try_write(pipe_fd[1], b"lost"), Err(Errno::EPIPE));
+        sigprocmask(SignalFlags::empty());
+        exit(0);
+    }
+    close(pipe_fd[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, -(SIGPIPE as i32));
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    pipe_size();
+    atomic_writes();
+    broken_pipe();
+    println!("pipetest2 passed!");
+    0
+}
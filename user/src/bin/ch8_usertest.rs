@@ -17,6 +17,7 @@ const TESTS: &[&str] = &[
     "ch5b_forktest2\0",
     "ch6b_filetest_simple\0",
     "ch7b_pipetest\0",
+    "ch7b_pipetest2\0",
     "ch8_deadlock_mutex1\0",
     "ch8_deadlock_sem1\0",
     "ch8_deadlock_sem2\0",
@@ -16,7 +16,7 @@ const LINE_START: &str = ">> ";
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::{close, dup2, exec, fork, open, pipe, waitpid, OpenFlags};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -130,42 +130,38 @@ pub fn main() -> i32 {
                                 let args_addr = &process_argument.args_addr;
                                 // redirect input
                                 if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
+                                    // the original fd is closed by exec
+                                    let input_fd = open(
+                                        input.as_str(),
+                                        OpenFlags::RDONLY | OpenFlags::CLOEXEC,
+                                    );
                                     if input_fd == -1 {
                                         println!("Error when opening file {}", input);
                                         return -4;
                                     }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
+                                    assert_eq!(dup2(input_fd as usize, 0), 0);
                                 }
                                 // redirect output
                                 if !output.is_empty() {
                                     let output_fd = open(
                                         output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
+                                        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::CLOEXEC,
                                     );
                                     if output_fd == -1 {
                                         println!("Error when opening file {}", output);
                                         return -4;
                                     }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
+                                    assert_eq!(dup2(output_fd as usize, 1), 1);
                                 }
                                 // receive input from the previous process
                                 if i > 0 {
-                                    close(0);
                                     let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
+                                    assert_eq!(dup2(read_end, 0), 0);
                                 }
                                 // send output to the next process
                                 if i < process_arguments_list.len() - 1 {
-                                    close(1);
                                     let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
+                                    assert_eq!(dup2(write_end, 1), 1);
                                 }
                                 // close all pipe ends inherited from the parent process
                                 for pipe_fd in pipes_fd.iter() {
@@ -0,0 +1,115 @@
+//! Error numbers returned by the kernel
+
+/// Error numbers, a failed syscall returns the negated value
+#[repr(isize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Errno {
+    /// Operation not permitted
+    EPERM = 1,
+    /// No such file or directory
+    ENOENT = 2,
+    /// No such process
+    ESRCH = 3,
+    /// I/O error
+    EIO = 5,
+    /// Argument list too long
+    E2BIG = 7,
+    /// Exec format error
+    ENOEXEC = 8,
+    /// Bad file descriptor
+    EBADF = 9,
+    /// No child processes
+    ECHILD = 10,
+    /// Try again
+    EAGAIN = 11,
+    /// Out of memory
+    ENOMEM = 12,
+    /// Permission denied
+    EACCES = 13,
+    /// Bad address
+    EFAULT = 14,
+    /// Device or resource busy
+    EBUSY = 16,
+    /// File exists
+    EEXIST = 17,
+    /// Not a directory
+    ENOTDIR = 20,
+    /// Is a directory
+    EISDIR = 21,
+    /// Invalid argument
+    EINVAL = 22,
+    /// Too many open files
+    EMFILE = 24,
+    /// File too large
+    EFBIG = 27,
+    /// No space left on device
+    ENOSPC = 28,
+    /// Illegal seek
+    ESPIPE = 29,
+    /// Broken pipe
+    EPIPE = 32,
+    /// Result too large
+    ERANGE = 34,
+    /// Resource deadlock would occur
+    EDEADLK = 35,
+    /// File name too long
+    ENAMETOOLONG = 36,
+    /// Function not implemented
+    ENOSYS = 38,
+    /// Directory not empty
+    ENOTEMPTY = 39,
+    /// Too many symbolic links encountered
+    ELOOP = 40,
+    /// A deadlock is detected, not a Linux errno
+    EDEADLOCKED = 0xDEAD,
+}
+
+impl Errno {
+    /// Get the error of a positive error number
+    pub fn from_code(code: isize) -> Option<Self> {
+        Some(match code {
+            1 => Self::EPERM,
+            2 => Self::ENOENT,
+            3 => Self::ESRCH,
+            5 => Self::EIO,
+            7 => Self::E2BIG,
+            8 => Self::ENOEXEC,
+            9 => Self::EBADF,
+            10 => Self::ECHILD,
+            11 => Self::EAGAIN,
+            12 => Self::ENOMEM,
+            13 => Self::EACCES,
+            14 => Self::EFAULT,
+            16 => Self::EBUSY,
+            17 => Self::EEXIST,
+            20 => Self::ENOTDIR,
+            21 => Self::EISDIR,
+            22 => Self::EINVAL,
+            24 => Self::EMFILE,
+            27 => Self::EFBIG,
+            28 => Self::ENOSPC,
+            29 => Self::ESPIPE,
+            32 => Self::EPIPE,
+            34 => Self::ERANGE,
+            35 => Self::EDEADLK,
+            36 => Self::ENAMETOOLONG,
+            38 => Self::ENOSYS,
+            39 => Self::ENOTEMPTY,
+            40 => Self::ELOOP,
+            0xDEAD => Self::EDEADLOCKED,
+            _ => return None,
+        })
+    }
+}
+
+/// Result of a syscall
+pub type SysResult<T = usize> = Result<T, Errno>;
+
+/// Turn the raw return value of a syscall into a [`SysResult`]
+pub fn check(ret: isize) -> SysResult {
+    if ret >= 0 {
+        Ok(ret as usize)
+    } else {
+        Err(Errno::from_code(-ret).unwrap_or(Errno::EINVAL))
+    }
+}
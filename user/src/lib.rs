@@ -5,6 +5,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod errno;
 mod lang_items;
 mod syscall;
 
@@ -13,17 +14,47 @@ extern crate core;
 #[macro_use]
 extern crate bitflags;
 
+use alloc::string::String;
 use alloc::vec::Vec;
-use buddy_system_allocator::LockedHeap;
+use buddy_system_allocator::Heap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::convert::TryInto;
+use core::ptr::NonNull;
 pub use console::{flush, STDIN, STDOUT};
+pub use errno::{check, Errno, SysResult};
+use spin::Mutex;
 pub use syscall::*;
 
-const USER_HEAP_SIZE: usize = 16384;
+/// the heap grows by at least this many bytes at a time
+const USER_HEAP_GROW_SIZE: usize = 16384;
 
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+/// A buddy heap which moves the program break when it runs out of space
+struct GrowingHeap(Mutex<Heap>);
+
+unsafe impl GlobalAlloc for GrowingHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // twice the rounded request always contains a block aligned to its size
+        let block = layout.size().max(layout.align()).next_power_of_two();
+        let size = (block * 2).max(USER_HEAP_GROW_SIZE);
+        let start = sbrk(size as isize);
+        if start == -1 {
+            return core::ptr::null_mut();
+        }
+        heap.add_to_heap(start as usize, start as usize + size);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
 
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: GrowingHeap = GrowingHeap(Mutex::new(Heap::empty()));
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -48,10 +79,6 @@ fn clear_bss() {
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     clear_bss();
-    unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
-    }
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -82,6 +109,93 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        /// fail if the path is not a directory
+        const DIRECTORY = 1 << 16;
+        /// close the fd on exec
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+bitflags! {
+    pub struct MapFlags: u32 {
+        /// stores are written back to the file
+        const SHARED = 1 << 0;
+        /// stores stay private to the process
+        const PRIVATE = 1 << 1;
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+bitflags! {
+    /// A set of signals, signal `signum` is the bit `1 << signum`
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
+        const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
+        const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
+        const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
+        const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
+        const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGILL: usize = 4;
+pub const SIGABRT: usize = 6;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+
+/// What to do when a signal is delivered
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// Address of a `fn(signum: usize)` handler, 0 for the default action.
+    /// The handler has to finish with [`sigreturn()`]
+    pub handler: usize,
+    /// Signals blocked while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: 0,
+            mask: SignalFlags::empty(),
+        }
     }
 }
 
@@ -142,8 +256,22 @@ pub struct Stat {
     pub mode: StatMode,
     /// number of hard links
     pub nlink: u32,
+    /// user ID of owner
+    pub uid: u32,
+    /// group ID of owner
+    pub gid: u32,
+    /// total size in bytes
+    pub size: u64,
+    /// time of last access in seconds
+    pub atime: u64,
+    /// time of last modification
+    pub mtime: u64,
+    /// time of last status change
+    pub ctime: u64,
+    /// permission bits, `mode` only holds the file type
+    pub perm: u32,
     /// unused pad
-    pad: [u64; 7],
+    pad: [u32; 3],
 }
 
 impl Stat {
@@ -153,11 +281,33 @@ impl Stat {
             ino: 0,
             mode: StatMode::NULL,
             nlink: 0,
-            pad: [0; 7],
+            uid: 0,
+            gid: 0,
+            size: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            perm: 0,
+            pad: [0; 3],
         }
     }
 }
 
+/// `struct timespec` of [`utimensat`]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// `nsec` of [`TimeSpec`]: set to the current time
+pub const UTIME_NOW: usize = (1 << 30) - 1;
+/// `nsec` of [`TimeSpec`]: keep the old time
+pub const UTIME_OMIT: usize = (1 << 30) - 2;
+/// flag of [`utimensat`] and [`fstatat`]: do not follow a symbolic link at the end of the path
+pub const AT_SYMLINK_NOFOLLOW: usize = 0x100;
+
 impl Default for Stat {
     fn default() -> Self {
         Self::new()
@@ -171,48 +321,268 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// symbolic link
+        const LNK   = 0o120000;
     }
 }
 
 const AT_FDCWD: isize = -100;
 
+/// Like libc, the wrappers below return -1 on failure,
+/// their `try_` variants return the [`Errno`] instead
+fn ret_code(result: SysResult) -> isize {
+    match result {
+        Ok(value) => value as isize,
+        Err(_) => -1,
+    }
+}
+
 pub fn open(path: &str, flags: OpenFlags) -> isize {
-    sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits)
+    ret_code(try_open(path, flags))
+}
+
+pub fn try_open(path: &str, flags: OpenFlags) -> SysResult {
+    check(sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits))
 }
 
 pub fn close(fd: usize) -> isize {
+    ret_code(try_close(fd))
+}
+
+pub fn try_close(fd: usize) -> SysResult {
     if fd == STDOUT {
         console::flush();
     }
-    sys_close(fd)
+    check(sys_close(fd))
 }
 
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
-    sys_read(fd, buf)
+    ret_code(try_read(fd, buf))
+}
+
+pub fn try_read(fd: usize, buf: &mut [u8]) -> SysResult {
+    check(sys_read(fd, buf))
 }
 
 pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+    ret_code(try_write(fd, buf))
+}
+
+pub fn try_write(fd: usize, buf: &[u8]) -> SysResult {
+    check(sys_write(fd, buf))
+}
+
+/// `whence` of [`lseek`]: from the start of the file
+pub const SEEK_SET: usize = 0;
+/// from the current offset
+pub const SEEK_CUR: usize = 1;
+/// from the end of the file
+pub const SEEK_END: usize = 2;
+
+/// Fails with ESPIPE on pipes and stdio
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    ret_code(try_lseek(fd, offset, whence))
+}
+
+pub fn try_lseek(fd: usize, offset: isize, whence: usize) -> SysResult {
+    check(sys_lseek(fd, offset, whence))
+}
+
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> isize {
+    ret_code(try_pread(fd, buf, offset))
+}
+
+pub fn try_pread(fd: usize, buf: &mut [u8], offset: usize) -> SysResult {
+    check(sys_pread64(fd, buf, offset))
+}
+
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> isize {
+    ret_code(try_pwrite(fd, buf, offset))
+}
+
+pub fn try_pwrite(fd: usize, buf: &[u8], offset: usize) -> SysResult {
+    check(sys_pwrite64(fd, buf, offset))
 }
 
 pub fn link(old_path: &str, new_path: &str) -> isize {
-    sys_linkat(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path, 0)
+    ret_code(try_link(old_path, new_path))
+}
+
+pub fn try_link(old_path: &str, new_path: &str) -> SysResult {
+    check(sys_linkat(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path, 0))
 }
 
 pub fn unlink(path: &str) -> isize {
-    sys_unlinkat(AT_FDCWD as usize, path, 0)
+    ret_code(try_unlink(path))
+}
+
+pub fn try_unlink(path: &str) -> SysResult {
+    check(sys_unlinkat(AT_FDCWD as usize, path, 0))
+}
+
+/// `target` is not checked, a relative one starts from the directory of `link_path`
+pub fn symlink(target: &str, link_path: &str) -> isize {
+    ret_code(try_symlink(target, link_path))
+}
+
+pub fn try_symlink(target: &str, link_path: &str) -> SysResult {
+    check(sys_symlinkat(target, AT_FDCWD as usize, link_path))
+}
+
+/// Copy the target of the symbolic link into `buf` without a trailing '\0',
+/// fails with EINVAL if `path` is not a symbolic link
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    ret_code(try_readlink(path, buf))
+}
+
+pub fn try_readlink(path: &str, buf: &mut [u8]) -> SysResult {
+    check(sys_readlinkat(AT_FDCWD as usize, path, buf))
+}
+
+/// `flags` of [`renameat2`]: fail with EEXIST instead of replacing `new_path`
+pub const RENAME_NOREPLACE: usize = 1 << 0;
+
+/// Replaces an existing `new_path` atomically
+pub fn rename(old_path: &str, new_path: &str) -> isize {
+    renameat2(old_path, new_path, 0)
+}
+
+pub fn try_rename(old_path: &str, new_path: &str) -> SysResult {
+    try_renameat2(old_path, new_path, 0)
+}
+
+pub fn renameat2(old_path: &str, new_path: &str, flags: usize) -> isize {
+    ret_code(try_renameat2(old_path, new_path, flags))
+}
+
+pub fn try_renameat2(old_path: &str, new_path: &str, flags: usize) -> SysResult {
+    check(sys_renameat2(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path, flags))
+}
+
+pub fn mkdir(path: &str) -> isize {
+    ret_code(try_mkdir(path))
+}
+
+pub fn try_mkdir(path: &str) -> SysResult {
+    check(sys_mkdirat(AT_FDCWD as usize, path, 0))
+}
+
+pub fn chdir(path: &str) -> isize {
+    ret_code(try_chdir(path))
+}
+
+pub fn try_chdir(path: &str) -> SysResult {
+    check(sys_chdir(path))
+}
+
+pub fn getcwd(buf: &mut [u8]) -> isize {
+    ret_code(try_getcwd(buf))
+}
+
+pub fn try_getcwd(buf: &mut [u8]) -> SysResult {
+    check(sys_getcwd(buf))
 }
 
 pub fn fstat(fd: usize, st: &Stat) -> isize {
-    sys_fstat(fd, st)
+    ret_code(try_fstat(fd, st))
+}
+
+pub fn try_fstat(fd: usize, st: &Stat) -> SysResult {
+    check(sys_fstat(fd, st))
+}
+
+/// Stat `path`, a symbolic link at its end is not followed with [`AT_SYMLINK_NOFOLLOW`]
+pub fn fstatat(path: &str, st: &Stat, flags: usize) -> isize {
+    ret_code(try_fstatat(path, st, flags))
+}
+
+pub fn try_fstatat(path: &str, st: &Stat, flags: usize) -> SysResult {
+    check(sys_fstatat(AT_FDCWD as usize, path, st, flags))
+}
+
+/// Only the rwx bits of `mode` are kept
+pub fn chmod(path: &str, mode: u32) -> isize {
+    ret_code(try_chmod(path, mode))
+}
+
+pub fn try_chmod(path: &str, mode: u32) -> SysResult {
+    check(sys_fchmodat(AT_FDCWD as usize, path, mode))
+}
+
+/// Set the access and modification times of `path`, both become the current time if
+/// `times` is None. The times are stored in whole seconds
+pub fn utimensat(path: &str, times: Option<&[TimeSpec; 2]>, flags: usize) -> isize {
+    ret_code(try_utimensat(path, times, flags))
+}
+
+pub fn try_utimensat(path: &str, times: Option<&[TimeSpec; 2]>, flags: usize) -> SysResult {
+    check(sys_utimensat(AT_FDCWD as usize, path, times, flags))
+}
+
+/// `d_type` of a directory
+pub const DT_DIR: u8 = 4;
+/// `d_type` of a regular file
+pub const DT_REG: u8 = 8;
+/// `d_type` of a symbolic link
+pub const DT_LNK: u8 = 10;
+
+/// An entry of a directory, read by [`getdents`]
+#[derive(Debug)]
+pub struct DirEntry {
+    pub ino: u64,
+    pub d_type: u8,
+    pub name: String,
+}
+
+impl DirEntry {
+    /// Parse the `linux_dirent64` records filled by [`getdents`]
+    pub fn parse(buf: &[u8]) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        // d_ino: u64, d_off: i64, d_reclen: u16, d_type: u8, then the name
+        while pos + 19 <= buf.len() {
+            let ino = u64::from_ne_bytes(buf[pos..pos + 8].try_into().unwrap());
+            let reclen = u16::from_ne_bytes(buf[pos + 16..pos + 18].try_into().unwrap()) as usize;
+            let d_type = buf[pos + 18];
+            let name = &buf[pos + 19..pos + reclen];
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            entries.push(DirEntry {
+                ino,
+                d_type,
+                name: String::from(core::str::from_utf8(&name[..len]).unwrap()),
+            });
+            pos += reclen;
+        }
+        entries
+    }
+}
+
+/// Fill `buf` with the next entries of the directory at `fd`, returns 0 at the end.
+/// Fails with ENOTDIR if `fd` is not a directory and with EINVAL if `buf` cannot hold an entry
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize {
+    ret_code(try_getdents(fd, buf))
+}
+
+pub fn try_getdents(fd: usize, buf: &mut [u8]) -> SysResult {
+    check(sys_getdents64(fd, buf))
 }
 
+/// Fails with EAGAIN if there is no message
 pub fn mail_read(buf: &mut [u8]) -> isize {
-    sys_mail_read(buf)
+    ret_code(try_mail_read(buf))
 }
 
+pub fn try_mail_read(buf: &mut [u8]) -> SysResult {
+    check(sys_mail_read(buf))
+}
+
+/// Fails with ESRCH if there is no process `pid` and with ENOSPC if its mailbox is full
 pub fn mail_write(pid: usize, buf: &[u8]) -> isize {
-    sys_mail_write(pid, buf)
+    ret_code(try_mail_write(pid, buf))
+}
+
+pub fn try_mail_write(pid: usize, buf: &[u8]) -> SysResult {
+    check(sys_mail_write(pid, buf))
 }
 
 pub fn exit(exit_code: i32) -> ! {
@@ -237,43 +607,104 @@ pub fn getpid() -> isize {
 }
 
 pub fn fork() -> isize {
-    sys_fork()
+    ret_code(try_fork())
+}
+
+pub fn try_fork() -> SysResult {
+    check(sys_fork())
 }
 
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
-    sys_exec(path, args)
+    ret_code(try_exec(path, args))
+}
+
+pub fn try_exec(path: &str, args: &[*const u8]) -> SysResult {
+    check(sys_exec(path, args))
 }
 
 pub fn set_priority(prio: isize) -> isize {
-    sys_set_priority(prio)
+    ret_code(try_set_priority(prio))
+}
+
+pub fn try_set_priority(prio: isize) -> SysResult {
+    check(sys_set_priority(prio))
 }
 
 pub fn wait(exit_code: &mut i32) -> isize {
+    ret_code(try_wait(exit_code))
+}
+
+pub fn try_wait(exit_code: &mut i32) -> SysResult {
     loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
+        match check(sys_waitpid(-1, exit_code as *mut _)) {
+            Err(Errno::EAGAIN) => {
                 sys_yield();
             }
-            n => {
-                return n;
+            result => {
+                return result;
             }
         }
     }
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    ret_code(try_waitpid(pid, exit_code))
+}
+
+pub fn try_waitpid(pid: usize, exit_code: &mut i32) -> SysResult {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
+        match check(sys_waitpid(pid as isize, exit_code as *mut _)) {
+            Err(Errno::EAGAIN) => {
                 sys_yield();
             }
-            n => {
-                return n;
+            result => {
+                return result;
             }
         }
     }
 }
 
+pub fn kill(pid: usize, signum: usize) -> isize {
+    ret_code(try_kill(pid, signum))
+}
+
+pub fn try_kill(pid: usize, signum: usize) -> SysResult {
+    check(sys_kill(pid, signum))
+}
+
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    ret_code(try_sigaction(signum, action, old_action))
+}
+
+pub fn try_sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> SysResult {
+    check(sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |a| a),
+        old_action.map_or(core::ptr::null_mut(), |a| a),
+    ))
+}
+
+/// Block the signals in `mask`, returns the previous mask
+pub fn sigprocmask(mask: SignalFlags) -> SignalFlags {
+    SignalFlags::from_bits_truncate(sys_sigprocmask(mask.bits) as u32)
+}
+
+pub fn sigreturn() -> isize {
+    ret_code(try_sigreturn())
+}
+
+pub fn try_sigreturn() -> SysResult {
+    check(sys_sigreturn())
+}
+
 pub fn sleep_blocking(sleep_ms: usize) {
     sys_sleep(sleep_ms);
 }
@@ -285,75 +716,266 @@ pub fn sleep(period_ms: usize) {
     }
 }
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    ret_code(try_mmap(start, len, prot))
+}
+
+pub fn try_mmap(start: usize, len: usize, prot: usize) -> SysResult {
+    check(sys_mmap(start, len, prot, MapFlags::ANONYMOUS.bits, 0, 0))
+}
+
+pub fn mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: MapFlags,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    ret_code(try_mmap_file(start, len, prot, flags, fd, offset))
+}
+
+pub fn try_mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: MapFlags,
+    fd: usize,
+    offset: usize,
+) -> SysResult {
+    check(sys_mmap(start, len, prot, flags.bits, fd, offset))
+}
+
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+/// Move the program break by `increment` bytes, returns the old break or -1
+pub fn sbrk(increment: isize) -> isize {
+    let old_brk = sys_brk(0);
+    if increment == 0 {
+        return old_brk;
+    }
+    if sys_brk((old_brk + increment) as usize) != old_brk + increment {
+        return -1;
+    }
+    old_brk
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
-    sys_munmap(start, len)
+    ret_code(try_munmap(start, len))
+}
+
+pub fn try_munmap(start: usize, len: usize) -> SysResult {
+    check(sys_munmap(start, len))
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    ret_code(try_mprotect(start, len, prot))
+}
+
+pub fn try_mprotect(start: usize, len: usize, prot: usize) -> SysResult {
+    check(sys_mprotect(start, len, prot))
 }
 
 pub fn spawn(path: &str) -> isize {
-    sys_spawn(path)
+    ret_code(try_spawn(path))
+}
+
+pub fn try_spawn(path: &str) -> SysResult {
+    check(sys_spawn(path, &[core::ptr::null()]))
+}
+
+pub fn spawn_args(path: &str, args: &[*const u8]) -> isize {
+    ret_code(try_spawn_args(path, args))
+}
+
+pub fn try_spawn_args(path: &str, args: &[*const u8]) -> SysResult {
+    check(sys_spawn(path, args))
 }
 
 pub fn dup(fd: usize) -> isize {
-    sys_dup(fd)
+    ret_code(try_dup(fd))
+}
+
+pub fn try_dup(fd: usize) -> SysResult {
+    check(sys_dup(fd))
+}
+/// Make `new_fd` refer to the file of `old_fd`, closing what `new_fd` referred to
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    ret_code(try_dup2(old_fd, new_fd))
+}
+
+pub fn try_dup2(old_fd: usize, new_fd: usize) -> SysResult {
+    if old_fd == new_fd {
+        // only check that old_fd is open
+        try_fcntl(old_fd, F_GETFD, 0)?;
+        return Ok(new_fd);
+    }
+    check(sys_dup3(old_fd, new_fd, 0))
+}
+pub fn dup3(old_fd: usize, new_fd: usize, flags: OpenFlags) -> isize {
+    ret_code(try_dup3(old_fd, new_fd, flags))
+}
+
+pub fn try_dup3(old_fd: usize, new_fd: usize, flags: OpenFlags) -> SysResult {
+    check(sys_dup3(old_fd, new_fd, flags.bits))
 }
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
-    sys_pipe(pipe_fd)
+    ret_code(try_pipe(pipe_fd))
+}
+
+pub fn try_pipe(pipe_fd: &mut [usize]) -> SysResult {
+    check(sys_pipe(pipe_fd))
+}
+
+/// fcntl command getting the fd flags
+pub const F_GETFD: usize = 1;
+/// fcntl command setting the fd flags
+pub const F_SETFD: usize = 2;
+/// The fd flag closing the fd on exec
+pub const FD_CLOEXEC: usize = 1;
+/// fcntl command getting the capacity of a pipe
+pub const F_GETPIPE_SZ: usize = 1032;
+/// fcntl command setting the capacity of a pipe, rounded up to pages
+pub const F_SETPIPE_SZ: usize = 1031;
+
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    ret_code(try_fcntl(fd, cmd, arg))
+}
+
+pub fn try_fcntl(fd: usize, cmd: usize, arg: usize) -> SysResult {
+    check(sys_fcntl(fd, cmd, arg))
 }
 
 pub fn task_info(info: &TaskInfo) -> isize {
-    sys_task_info(info)
+    ret_code(try_task_info(info))
+}
+
+pub fn try_task_info(info: &TaskInfo) -> SysResult {
+    check(sys_task_info(info))
 }
 
 pub fn thread_create(entry: usize, arg: usize) -> isize {
-    sys_thread_create(entry, arg)
+    ret_code(try_thread_create(entry, arg))
+}
+
+pub fn try_thread_create(entry: usize, arg: usize) -> SysResult {
+    check(sys_thread_create(entry, arg))
 }
+
 pub fn gettid() -> isize {
     sys_gettid()
 }
+
+/// Wait for thread `tid` to exit, returns its exit code, or -1 on failure
 pub fn waittid(tid: usize) -> isize {
+    let mut exit_code: i32 = 0;
+    match try_waittid(tid, &mut exit_code) {
+        Ok(_) => exit_code as isize,
+        Err(_) => -1,
+    }
+}
+
+/// Wait for thread `tid` to exit and store its exit code, returns `tid`
+pub fn try_waittid(tid: usize, exit_code: &mut i32) -> SysResult {
     loop {
-        match sys_waittid(tid) {
-            -2 => {
+        match check(sys_waittid(tid, exit_code as *mut _)) {
+            Err(Errno::EAGAIN) => {
                 yield_();
             }
-            exit_code => return exit_code,
+            result => {
+                return result;
+            }
         }
     }
 }
 
 pub fn mutex_create() -> isize {
-    sys_mutex_create(false)
+    ret_code(try_mutex_create())
+}
+
+pub fn try_mutex_create() -> SysResult {
+    check(sys_mutex_create(false))
 }
+
 pub fn mutex_blocking_create() -> isize {
-    sys_mutex_create(true)
+    ret_code(try_mutex_blocking_create())
+}
+
+pub fn try_mutex_blocking_create() -> SysResult {
+    check(sys_mutex_create(true))
 }
+
+/// Returns -0xdead instead of -1 if locking would deadlock, see [`enable_deadlock_detect`]
 pub fn mutex_lock(mutex_id: usize) -> isize {
     sys_mutex_lock(mutex_id)
 }
+
+pub fn try_mutex_lock(mutex_id: usize) -> SysResult {
+    check(sys_mutex_lock(mutex_id))
+}
+
 pub fn mutex_unlock(mutex_id: usize) {
     sys_mutex_unlock(mutex_id);
 }
+
+pub fn try_mutex_unlock(mutex_id: usize) -> SysResult {
+    check(sys_mutex_unlock(mutex_id))
+}
+
 pub fn semaphore_create(res_count: usize) -> isize {
-    sys_semaphore_create(res_count)
+    ret_code(try_semaphore_create(res_count))
 }
+
+pub fn try_semaphore_create(res_count: usize) -> SysResult {
+    check(sys_semaphore_create(res_count))
+}
+
 pub fn semaphore_up(sem_id: usize) {
     sys_semaphore_up(sem_id);
 }
+
+pub fn try_semaphore_up(sem_id: usize) -> SysResult {
+    check(sys_semaphore_up(sem_id))
+}
+
 pub fn enable_deadlock_detect(enabled: bool) -> isize {
-    sys_enable_deadlock_detect(enabled as usize)
+    ret_code(try_enable_deadlock_detect(enabled))
 }
+
+pub fn try_enable_deadlock_detect(enabled: bool) -> SysResult {
+    check(sys_enable_deadlock_detect(enabled as usize))
+}
+
+/// Returns -0xdead instead of -1 if waiting would deadlock, see [`enable_deadlock_detect`]
 pub fn semaphore_down(sem_id: usize) -> isize {
     sys_semaphore_down(sem_id)
 }
+
+pub fn try_semaphore_down(sem_id: usize) -> SysResult {
+    check(sys_semaphore_down(sem_id))
+}
+
 pub fn condvar_create() -> isize {
-    sys_condvar_create(0)
+    ret_code(try_condvar_create())
+}
+
+pub fn try_condvar_create() -> SysResult {
+    check(sys_condvar_create(0))
 }
+
 pub fn condvar_signal(condvar_id: usize) {
     sys_condvar_signal(condvar_id);
 }
+
+pub fn try_condvar_signal(condvar_id: usize) -> SysResult {
+    check(sys_condvar_signal(condvar_id))
+}
+
 pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
     sys_condvar_wait(condvar_id, mutex_id);
 }
+
+pub fn try_condvar_wait(condvar_id: usize, mutex_id: usize) -> SysResult {
+    check(sys_condvar_wait(condvar_id, mutex_id))
+}
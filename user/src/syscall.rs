@@ -1,30 +1,51 @@
-use crate::TaskInfo;
+use crate::{SignalAction, TaskInfo, TimeSpec};
 
 use super::{Stat, TimeVal};
 
+pub const SYSCALL_GETCWD: usize = 17;
+pub const SYSCALL_CHDIR: usize = 49;
+pub const SYSCALL_FCHMODAT: usize = 53;
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
+pub const SYSCALL_GETDENTS64: usize = 61;
+pub const SYSCALL_LSEEK: usize = 62;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_PREAD64: usize = 67;
+pub const SYSCALL_PWRITE64: usize = 68;
+pub const SYSCALL_MKDIRAT: usize = 34;
 pub const SYSCALL_UNLINKAT: usize = 35;
+pub const SYSCALL_SYMLINKAT: usize = 36;
 pub const SYSCALL_LINKAT: usize = 37;
+pub const SYSCALL_READLINKAT: usize = 78;
+pub const SYSCALL_FSTATAT: usize = 79;
 pub const SYSCALL_FSTAT: usize = 80;
+pub const SYSCALL_UTIMENSAT: usize = 88;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_SIGRETURN: usize = 139;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
+pub const SYSCALL_RENAMEAT2: usize = 276;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
+pub const SYSCALL_BRK: usize = 214;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
+pub const SYSCALL_DUP3: usize = 23;
 pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_FCNTL: usize = 25;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
@@ -99,6 +120,44 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len()])
 }
 
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence])
+}
+
+pub fn sys_pread64(fd: usize, buffer: &mut [u8], offset: usize) -> isize {
+    syscall6(
+        SYSCALL_PREAD64,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), offset, 0, 0],
+    )
+}
+
+pub fn sys_pwrite64(fd: usize, buffer: &[u8], offset: usize) -> isize {
+    syscall6(
+        SYSCALL_PWRITE64,
+        [fd, buffer.as_ptr() as usize, buffer.len(), offset, 0, 0],
+    )
+}
+
+pub fn sys_renameat2(
+    old_dirfd: usize,
+    old_path: &str,
+    new_dirfd: usize,
+    new_path: &str,
+    flags: usize,
+) -> isize {
+    syscall6(
+        SYSCALL_RENAMEAT2,
+        [
+            old_dirfd,
+            old_path.as_ptr() as usize,
+            new_dirfd,
+            new_path.as_ptr() as usize,
+            flags,
+            0,
+        ],
+    )
+}
+
 pub fn sys_linkat(
     old_dirfd: usize,
     old_path: &str,
@@ -123,10 +182,62 @@ pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
 
+pub fn sys_mkdirat(dirfd: usize, path: &str, mode: usize) -> isize {
+    syscall(SYSCALL_MKDIRAT, [dirfd, path.as_ptr() as usize, mode])
+}
+
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
+pub fn sys_getcwd(buffer: &mut [u8]) -> isize {
+    syscall(
+        SYSCALL_GETCWD,
+        [buffer.as_mut_ptr() as usize, buffer.len(), 0],
+    )
+}
+
+pub fn sys_getdents64(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_GETDENTS64, [fd, buffer.as_mut_ptr() as usize, buffer.len()])
+}
+
+pub fn sys_symlinkat(target: &str, new_dirfd: usize, link_path: &str) -> isize {
+    syscall(
+        SYSCALL_SYMLINKAT,
+        [target.as_ptr() as usize, new_dirfd, link_path.as_ptr() as usize],
+    )
+}
+
+pub fn sys_readlinkat(dirfd: usize, path: &str, buffer: &mut [u8]) -> isize {
+    syscall6(
+        SYSCALL_READLINKAT,
+        [dirfd, path.as_ptr() as usize, buffer.as_mut_ptr() as usize, buffer.len(), 0, 0],
+    )
+}
+
 pub fn sys_fstat(fd: usize, st: &Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
 }
 
+pub fn sys_fstatat(dirfd: usize, path: &str, st: &Stat, flags: usize) -> isize {
+    syscall6(
+        SYSCALL_FSTATAT,
+        [dirfd, path.as_ptr() as usize, st as *const _ as usize, flags, 0, 0],
+    )
+}
+
+pub fn sys_fchmodat(dirfd: usize, path: &str, mode: u32) -> isize {
+    syscall(SYSCALL_FCHMODAT, [dirfd, path.as_ptr() as usize, mode as usize])
+}
+
+pub fn sys_utimensat(dirfd: usize, path: &str, times: Option<&[TimeSpec; 2]>, flags: usize) -> isize {
+    let times = times.map_or(0, |times| times.as_ptr() as usize);
+    syscall6(
+        SYSCALL_UTIMENSAT,
+        [dirfd, path.as_ptr() as usize, times, flags, 0, 0],
+    )
+}
+
 pub fn sys_mail_read(buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_MAIL_READ,
@@ -154,6 +265,29 @@ pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum, 0])
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [mask as usize, 0, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
 pub fn sys_get_time(time: &TimeVal, tz: usize) -> isize {
     syscall(SYSCALL_GETTIMEOFDAY, [time as *const _ as usize, tz, 0])
 }
@@ -181,22 +315,48 @@ pub fn sys_set_priority(prio: isize) -> isize {
     syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
 }
 
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
-    syscall(SYSCALL_MMAP, [start, len, prot])
+pub fn sys_mmap(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: u32,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    syscall6(SYSCALL_MMAP, [start, len, prot, flags as usize, fd, offset])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
 }
 
 pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
-pub fn sys_spawn(path: &str) -> isize {
-    syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [start, len, prot])
+}
+
+pub fn sys_spawn(path: &str, args: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_SPAWN,
+        [path.as_ptr() as usize, args.as_ptr() as usize, 0],
+    )
 }
 
 pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
@@ -213,8 +373,8 @@ pub fn sys_gettid() -> isize {
     syscall(SYSCALL_GETTID, [0; 3])
 }
 
-pub fn sys_waittid(tid: usize) -> isize {
-    syscall(SYSCALL_WAITTID, [tid, 0, 0])
+pub fn sys_waittid(tid: usize, exit_code: *mut i32) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, exit_code as usize, 0])
 }
 
 pub fn sys_mutex_create(blocking: bool) -> isize {